@@ -0,0 +1,383 @@
+/*!
+Merkle airdrops.
+
+The owner commits to a list of `entry_count` entries of `(account_id, amount)` with the root of a
+Merkle tree and escrows `total_amount` with `create_airdrop`. Each recipient claims its own entry with
+`claim_airdrop` and a proof, so nothing is spent on accounts that never claim. A leaf is the
+`env::sha256` hash of the borsh serialization of `AirdropLeaf`:
+
+```text
+index: u32              position of the leaf in the tree
+account_id: AccountId
+amount: u128
+```
+
+Each level of the proof hashes the concatenation of two 32-byte nodes. Bit `i` of `index` tells
+whether the node at level `i` is the right child (1) or the left one (0). Claimed indexes are
+kept in a bitmap, which the owner pays for in full when creating the airdrop. Once the airdrop
+expires or all of it is claimed, `close_airdrop` returns what was left unclaimed to the owner,
+removes the airdrop with its bitmap and refunds their storage to the owner.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::{Base64VecU8, U64};
+use serde::Deserialize;
+use std::convert::TryInto;
+
+const MAX_PROOF_LENGTH: usize = 32;
+const MAX_AIRDROP_ENTRIES: u32 = 32_768;
+const CLAIMS_PER_WORD: u32 = 128;
+
+#[derive(BorshSerialize)]
+pub struct AirdropLeaf {
+    pub index: u32,
+    pub account_id: AccountId,
+    pub amount: Balance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Airdrop {
+    merkle_root: [u8; 32],
+    total_amount: Balance,
+    claimed_amount: Balance,
+    entry_count: u32,
+    expires_at: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AirdropProof {
+    pub index: u32,
+    pub hashes: Vec<Base64VecU8>,
+}
+
+#[derive(Serialize)]
+pub struct AirdropView {
+    merkle_root: Base64VecU8,
+    total_amount: U128,
+    claimed_amount: U128,
+    entry_count: u32,
+    expires_at: U64,
+}
+
+#[derive(Serialize)]
+struct AirdropCreated<'a> {
+    airdrop_id: u64,
+    merkle_root: &'a Base64VecU8,
+    total_amount: U128,
+    entry_count: u32,
+    expires_at: U64,
+}
+
+#[derive(Serialize)]
+struct AirdropClaimed<'a> {
+    airdrop_id: u64,
+    account_id: &'a AccountId,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+struct AirdropClosed {
+    airdrop_id: u64,
+    unclaimed_amount: U128,
+}
+
+/// Returns the root of the tree that contains `leaf` at `index`, according to `proof`.
+fn merkle_root(leaf: &AirdropLeaf, proof: &[Base64VecU8]) -> Vec<u8> {
+    let mut node = env::sha256(&leaf.try_to_vec().unwrap());
+    for (level, sibling) in proof.iter().enumerate() {
+        require!(sibling.0.len() == 32, "Proof hashes must be 32 bytes");
+        node = match (leaf.index >> level) & 1 {
+            0 => env::sha256(&[node.as_slice(), &sibling.0].concat()),
+            _ => env::sha256(&[sibling.0.as_slice(), &node].concat()),
+        };
+    }
+    node
+}
+
+/// Returns the number of bitmap words that hold the claims of `entry_count` entries.
+fn claim_words(entry_count: u32) -> u32 {
+    entry_count.div_ceil(CLAIMS_PER_WORD)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `total_amount` from the owner for the `entry_count` entries committed to by
+    /// `merkle_root`, and returns the id of the airdrop.
+    #[payable]
+    pub fn create_airdrop(
+        &mut self,
+        merkle_root: Base64VecU8,
+        total_amount: U128,
+        entry_count: u32,
+        expires_at: U64,
+    ) -> u64 {
+        self.only_owner();
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(total_amount.0 > 0, "The amount should be a positive number");
+        require!(
+            entry_count > 0 && entry_count <= MAX_AIRDROP_ENTRIES,
+            "The airdrop must have between 1 and 32768 entries"
+        );
+        require!(expires_at.0 > env::block_timestamp(), "The airdrop must expire in the future");
+        let root: [u8; 32] = merkle_root
+            .0
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Merkle root must be 32 bytes"));
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.owner_id.clone();
+        self.internal_lock_escrow(Lock::Airdrop, &owner_id, total_amount.0, "airdrop");
+
+        let airdrop_id = self.next_airdrop_id;
+        self.next_airdrop_id += 1;
+        self.airdrops.insert(
+            &airdrop_id,
+            &Airdrop {
+                merkle_root: root,
+                total_amount: total_amount.0,
+                claimed_amount: 0,
+                entry_count,
+                expires_at: expires_at.0,
+            },
+        );
+        // The whole bitmap is written up front, so that the owner pays for it.
+        for word in 0..claim_words(entry_count) {
+            self.airdrop_claims.insert(&(airdrop_id, word), &0);
+        }
+        emit_nep297_event(
+            "airdrop_created",
+            &[AirdropCreated {
+                airdrop_id,
+                merkle_root: &merkle_root,
+                total_amount,
+                entry_count,
+                expires_at,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        airdrop_id
+    }
+
+    /// Pays out the caller's entry in the airdrop, which is proven by `proof`.
+    #[payable]
+    pub fn claim_airdrop(&mut self, airdrop_id: u64, amount: U128, proof: AirdropProof) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(!self.internal_is_frozen(&account_id), "Receiver account is frozen");
+        let mut airdrop =
+            self.airdrops.get(&airdrop_id).unwrap_or_else(|| env::panic_str("Airdrop not found"));
+        require!(env::block_timestamp() < airdrop.expires_at, "The airdrop has expired");
+        require!(proof.hashes.len() <= MAX_PROOF_LENGTH, "The proof is too long");
+        require!(proof.index < airdrop.entry_count, "The index is out of range");
+        let leaf = AirdropLeaf { index: proof.index, account_id, amount: amount.into() };
+        require!(merkle_root(&leaf, &proof.hashes) == airdrop.merkle_root, "Invalid proof");
+
+        let word_key = (airdrop_id, proof.index / CLAIMS_PER_WORD);
+        let bit = 1u128 << (proof.index % CLAIMS_PER_WORD);
+        let word = self.airdrop_claims.get(&word_key).unwrap_or(0);
+        require!(word & bit == 0, "The airdrop was already claimed");
+        self.airdrop_claims.insert(&word_key, &(word | bit));
+        airdrop.claimed_amount += leaf.amount;
+        require!(
+            airdrop.claimed_amount <= airdrop.total_amount,
+            "The airdrop doesn't have enough tokens left"
+        );
+        self.airdrops.insert(&airdrop_id, &airdrop);
+
+        self.internal_release_escrow(
+            Lock::Airdrop,
+            &leaf.account_id,
+            leaf.amount,
+            Some("airdrop".to_string()),
+        );
+        emit_nep297_event(
+            "airdrop_claimed",
+            &[AirdropClaimed { airdrop_id, account_id: &leaf.account_id, amount }],
+        );
+        amount
+    }
+
+    /// Returns what was left unclaimed in an airdrop that expired or was claimed in full to the
+    /// owner, with the storage of the airdrop. Anyone can call it.
+    pub fn close_airdrop(&mut self, airdrop_id: u64) -> U128 {
+        let airdrop =
+            self.airdrops.get(&airdrop_id).unwrap_or_else(|| env::panic_str("Airdrop not found"));
+        require!(
+            airdrop.expires_at <= env::block_timestamp()
+                || airdrop.claimed_amount == airdrop.total_amount,
+            "The airdrop has not expired yet"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.airdrops.remove(&airdrop_id);
+        for word in 0..claim_words(airdrop.entry_count) {
+            self.airdrop_claims.remove(&(airdrop_id, word));
+        }
+        let storage_refund =
+            Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost();
+        Promise::new(self.owner_id.clone()).transfer(storage_refund);
+        let unclaimed_amount = airdrop.total_amount - airdrop.claimed_amount;
+        if unclaimed_amount > 0 {
+            let owner_id = self.owner_id.clone();
+            self.internal_release_escrow(
+                Lock::Airdrop,
+                &owner_id,
+                unclaimed_amount,
+                Some("airdrop expired".to_string()),
+            );
+        }
+        emit_nep297_event(
+            "airdrop_closed",
+            &[AirdropClosed { airdrop_id, unclaimed_amount: unclaimed_amount.into() }],
+        );
+        unclaimed_amount.into()
+    }
+
+    pub fn get_airdrop(&self, airdrop_id: u64) -> Option<AirdropView> {
+        self.airdrops.get(&airdrop_id).map(|airdrop| AirdropView {
+            merkle_root: airdrop.merkle_root.to_vec().into(),
+            total_amount: airdrop.total_amount.into(),
+            claimed_amount: airdrop.claimed_amount.into(),
+            entry_count: airdrop.entry_count,
+            expires_at: airdrop.expires_at.into(),
+        })
+    }
+
+    pub fn is_airdrop_claimed(&self, airdrop_id: u64, index: u32) -> bool {
+        let word = self.airdrop_claims.get(&(airdrop_id, index / CLAIMS_PER_WORD)).unwrap_or(0);
+        word & (1u128 << (index % CLAIMS_PER_WORD)) != 0
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    const EXPIRES_AT: u64 = 1_000_000_000;
+
+    fn leaf_hash(index: u32, account_id: AccountId, amount: Balance) -> Vec<u8> {
+        env::sha256(&AirdropLeaf { index, account_id, amount }.try_to_vec().unwrap())
+    }
+
+    fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+        env::sha256(&[left, right].concat())
+    }
+
+    /// Builds a tree of four entries for accounts 0 to 3, and returns the root and the leaves.
+    fn setup() -> (VMContextBuilder, Contract, Vec<Vec<u8>>) {
+        let (context, mut contract) = setup_with(&[accounts(1), accounts(3)], DEPOSIT);
+        let leaves: Vec<_> =
+            (0..4).map(|index| leaf_hash(index, accounts(index as usize), 100)).collect();
+        let root =
+            node_hash(&node_hash(&leaves[0], &leaves[1]), &node_hash(&leaves[2], &leaves[3]));
+        contract.create_airdrop(root.into(), 400.into(), 4, EXPIRES_AT.into());
+        (context, contract, leaves)
+    }
+
+    #[test]
+    fn claim_and_close_airdrop() {
+        let (mut context, mut contract, leaves) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 400);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let proof = AirdropProof {
+            index: 3,
+            hashes: vec![leaves[2].clone().into(), node_hash(&leaves[0], &leaves[1]).into()],
+        };
+        assert_eq!(contract.claim_airdrop(0, 100.into(), proof).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        assert!(contract.is_airdrop_claimed(0, 3));
+        assert!(!contract.is_airdrop_claimed(0, 1));
+        assert_eq!(contract.get_airdrop(0).unwrap().claimed_amount.0, 100);
+
+        testing_env!(context.block_timestamp(EXPIRES_AT).build());
+        assert_eq!(contract.close_airdrop(0).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+        assert!(contract.get_airdrop(0).is_none());
+        assert!(contract.airdrop_claims.get(&(0, 0)).is_none());
+    }
+
+    #[test]
+    fn close_claimed_airdrop() {
+        let (mut context, mut contract, leaves) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let root = node_hash(&leaves[0], &leaves[1]);
+        let storage_usage = env::storage_usage();
+        contract.create_airdrop(root.into(), 100.into(), 2, EXPIRES_AT.into());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let proof = AirdropProof { index: 1, hashes: vec![leaves[0].clone().into()] };
+        contract.claim_airdrop(1, 100.into(), proof);
+
+        assert_eq!(contract.close_airdrop(1).0, 0);
+        assert!(contract.get_airdrop(1).is_none());
+        assert_eq!(env::storage_usage(), storage_usage);
+    }
+
+    #[test]
+    #[should_panic(expected = "The index is out of range")]
+    fn claim_airdrop_index_fail() {
+        let (mut context, mut contract, leaves) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let root = node_hash(&leaves[0], &leaves[1]);
+        contract.create_airdrop(root.into(), 100.into(), 1, EXPIRES_AT.into());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let proof = AirdropProof { index: 1, hashes: vec![leaves[0].clone().into()] };
+        contract.claim_airdrop(1, 100.into(), proof);
+    }
+
+    #[test]
+    #[should_panic(expected = "The airdrop was already claimed")]
+    fn claim_airdrop_twice_fail() {
+        let (mut context, mut contract, leaves) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let proof = || AirdropProof {
+            index: 1,
+            hashes: vec![leaves[0].clone().into(), node_hash(&leaves[2], &leaves[3]).into()],
+        };
+        contract.claim_airdrop(0, 100.into(), proof());
+        contract.claim_airdrop(0, 100.into(), proof());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid proof")]
+    fn claim_airdrop_amount_fail() {
+        let (mut context, mut contract, leaves) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let proof = AirdropProof {
+            index: 1,
+            hashes: vec![leaves[0].clone().into(), node_hash(&leaves[2], &leaves[3]).into()],
+        };
+        contract.claim_airdrop(0, 200.into(), proof);
+    }
+}
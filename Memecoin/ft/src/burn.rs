@@ -0,0 +1,135 @@
+/*!
+Deflationary burn on transfers.
+
+While `use_burn_on_transfer` is on, `burn_on_transfer_bps` of every transfer is taken from the
+sender and destroyed, until the total supply reaches `burn_floor_supply`.
+*/
+use crate::*;
+use near_contract_standards::fungible_token::events::FtBurn;
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn update_use_burn_on_transfer(&mut self, use_burn_on_transfer: bool) {
+        self.only_owner();
+        assert_one_yocto();
+        self.use_burn_on_transfer = use_burn_on_transfer;
+        emit_event("update_use_burn_on_transfer", &use_burn_on_transfer.to_string());
+    }
+
+    pub fn get_use_burn_on_transfer(&self) -> bool {
+        self.use_burn_on_transfer
+    }
+
+    /// Sets the share of each transfer that is burned, in basis points.
+    #[payable]
+    pub fn set_burn_on_transfer_bps(&mut self, burn_bps: u16) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(burn_bps < MAX_BPS, "Burn must be lower than 10000 basis points");
+        require!(
+            burn_bps + self.reflection_fee_bps < MAX_BPS,
+            "Transfer fees must be lower than 10000 basis points"
+        );
+        self.burn_on_transfer_bps = burn_bps;
+        emit_event("set_burn_on_transfer_bps", &burn_bps.to_string());
+    }
+
+    pub fn get_burn_on_transfer_bps(&self) -> u16 {
+        self.burn_on_transfer_bps
+    }
+
+    /// Sets the total supply below which transfers stop burning.
+    #[payable]
+    pub fn set_burn_floor_supply(&mut self, floor_supply: U128) {
+        self.only_owner();
+        assert_one_yocto();
+        self.burn_floor_supply = floor_supply.into();
+        emit_event("set_burn_floor_supply", &floor_supply.0.to_string());
+    }
+
+    pub fn get_burn_floor_supply(&self) -> U128 {
+        self.burn_floor_supply.into()
+    }
+}
+
+impl Contract {
+    /// Returns the share of a transfer of `amount` that is burned.
+    pub(crate) fn internal_transfer_burn_amount(&self, amount: Balance) -> Balance {
+        if !self.use_burn_on_transfer {
+            return 0;
+        }
+        let burnable = self.ft_total_supply().0.saturating_sub(self.burn_floor_supply);
+        mul_div(amount, self.burn_on_transfer_bps.into(), MAX_BPS.into()).min(burnable)
+    }
+
+    pub(crate) fn internal_burn_on_transfer(&mut self, account_id: &AccountId, amount: Balance) {
+        self.internal_withdraw(account_id, amount);
+        self.internal_record_supply_change(SupplyChangeKind::Burn, account_id, amount);
+        FtBurn { owner_id: account_id, amount: &U128(amount), memo: Some("burn on transfer") }
+            .emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::ONE_YOCTO;
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1)], ONE_YOCTO);
+        contract.update_use_burn_on_transfer(true);
+        contract.set_burn_on_transfer_bps(1_000);
+        contract.set_burn_floor_supply((TOTAL_SUPPLY * 19 / 20).into());
+        (context, contract)
+    }
+
+    #[test]
+    fn burn_on_transfer() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"ft_burn\"")));
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY * 39 / 40);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY * 9 / 40);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY * 3 / 4);
+
+        // Only what is left above the floor is burned.
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 2).into(), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY * 19 / 20);
+        assert_eq!(
+            contract.ft_balance_of(accounts(1)).0,
+            TOTAL_SUPPLY * 9 / 40 + TOTAL_SUPPLY * 19 / 40
+        );
+
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 10).into(), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY * 19 / 20);
+    }
+
+    #[test]
+    fn renounce_burn_on_transfer() {
+        let (_, mut contract) = setup();
+        contract.update_use_burn_on_transfer(false);
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY / 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Burn must be lower than 10000 basis points")]
+    fn set_burn_on_transfer_bps_fail() {
+        let (_, mut contract) = setup();
+        contract.set_burn_on_transfer_bps(10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfer fees must be lower than 10000 basis points")]
+    fn set_transfer_fees_fail() {
+        let (_, mut contract) = setup();
+        contract.set_reflection_fee_bps(8_000);
+        contract.set_burn_on_transfer_bps(1_999);
+        contract.set_burn_on_transfer_bps(2_000);
+    }
+}
@@ -0,0 +1,247 @@
+/*!
+Pro-rata NEAR dividends for token holders.
+
+`distribute_dividends` spreads the attached NEAR over the holders by raising
+`dividends_per_share`, scaled by `DIVIDEND_MAGNITUDE`. An account is owed
+`balance * (dividends_per_share - debt)` on top of what was already credited to it, where `debt`
+is the value of the accumulator when it was last settled. Accounts are settled right before their
+balance changes, so transfers, mints and burns never move dividends that were earned before them.
+
+Tokens held by the contract account itself, such as escrowed transfers, do not earn dividends.
+An account pays for its dividend record out of its available storage balance when it is first
+settled, and a balance change it can't pay the record of fails.
+*/
+use crate::*;
+use near_sdk::PromiseResult;
+
+const DIVIDEND_MAGNITUDE: u128 = 1_000_000_000_000_000_000_000_000;
+const GAS_FOR_ON_DIVIDENDS_CLAIMED: Gas = Gas(5_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct DividendAccount {
+    debt: u128,
+    credited: Balance,
+}
+
+#[derive(Serialize)]
+struct DividendsDistributed<'a> {
+    sender_id: &'a AccountId,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+struct DividendsClaimed<'a> {
+    account_id: &'a AccountId,
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Distributes the attached NEAR to all holders in proportion to their balances.
+    #[payable]
+    pub fn distribute_dividends(&mut self) {
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let eligible_supply = self.token.total_supply
+            - self.token.accounts.get(&env::current_account_id()).unwrap_or(0);
+        require!(eligible_supply > 0, "No holders to distribute to");
+        self.dividends_per_share += mul_div(amount, DIVIDEND_MAGNITUDE, eligible_supply);
+        emit_nep297_event(
+            "dividends_distributed",
+            &[DividendsDistributed {
+                sender_id: &env::predecessor_account_id(),
+                amount: amount.into(),
+            }],
+        );
+    }
+
+    pub fn withdrawable_dividend_of(&self, account_id: AccountId) -> U128 {
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        let dividends = self.dividend_accounts.get(&account_id).unwrap_or_default();
+        (dividends.credited + self.internal_earned_dividends(&account_id, balance, dividends.debt))
+            .into()
+    }
+
+    /// Sends the caller the NEAR dividends it is owed.
+    #[payable]
+    pub fn claim_dividends(&mut self) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        self.internal_settle_dividends(&account_id, balance);
+        let mut dividends = self.dividend_accounts.get(&account_id).unwrap_or_default();
+        let amount = std::mem::take(&mut dividends.credited);
+        require!(amount > 0, "No dividends to claim");
+        self.dividend_accounts.insert(&account_id, &dividends);
+        emit_nep297_event(
+            "dividends_claimed",
+            &[DividendsClaimed { account_id: &account_id, amount: amount.into() }],
+        );
+
+        Promise::new(account_id.clone()).transfer(amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_ON_DIVIDENDS_CLAIMED)
+                .on_dividends_claimed(account_id, amount.into()),
+        )
+    }
+
+    /// Credits the dividends back to `account_id` if sending them failed.
+    #[private]
+    pub fn on_dividends_claimed(&mut self, account_id: AccountId, amount: U128) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+        let mut dividends = self.dividend_accounts.get(&account_id).unwrap_or_default();
+        dividends.credited += amount.0;
+        self.dividend_accounts.insert(&account_id, &dividends);
+        log!("Restored {} yoctoNEAR of dividends to @{}", amount.0, account_id);
+        false
+    }
+}
+
+impl Contract {
+    fn internal_earned_dividends(
+        &self,
+        account_id: &AccountId,
+        balance: Balance,
+        debt: u128,
+    ) -> Balance {
+        if balance == 0 || account_id == &env::current_account_id() {
+            return 0;
+        }
+        mul_div(balance, self.dividends_per_share - debt, DIVIDEND_MAGNITUDE)
+    }
+
+    /// Credits `account_id` with the dividends earned on `balance` since it was last settled.
+    /// Must be called with the balance it had up to now, before the balance changes. The account
+    /// pays for a new record.
+    pub(crate) fn internal_settle_dividends(&mut self, account_id: &AccountId, balance: Balance) {
+        let mut dividends = self.dividend_accounts.get(account_id).unwrap_or_default();
+        if dividends.debt == self.dividends_per_share {
+            return;
+        }
+        let initial_storage_usage = env::storage_usage();
+        dividends.credited += self.internal_earned_dividends(account_id, balance, dividends.debt);
+        dividends.debt = self.dividends_per_share;
+        self.dividend_accounts.insert(account_id, &dividends);
+        self.internal_charge_storage(account_id, initial_storage_usage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, ONE_NEAR, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1), accounts(4)], DEPOSIT);
+        for account_id in [accounts(1), accounts(2)].iter() {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(DEPOSIT)
+                .predecessor_account_id(account_id.clone())
+                .build());
+            contract.storage_deposit(None, None);
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn dividends_follow_balances() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(4 * ONE_NEAR)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.distribute_dividends();
+        assert_eq!(contract.withdrawable_dividend_of(accounts(1)).0, ONE_NEAR);
+        assert_eq!(contract.withdrawable_dividend_of(accounts(2)).0, 3 * ONE_NEAR);
+
+        // Dividends earned before a transfer stay with the sender.
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        testing_env!(context
+            .attached_deposit(2 * ONE_NEAR)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.distribute_dividends();
+        assert_eq!(contract.withdrawable_dividend_of(accounts(1)).0, 2 * ONE_NEAR);
+        assert_eq!(contract.withdrawable_dividend_of(accounts(2)).0, 4 * ONE_NEAR);
+
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.update_use_mint(true);
+        contract.mint(TOTAL_SUPPLY.into());
+        assert_eq!(contract.withdrawable_dividend_of(accounts(2)).0, 4 * ONE_NEAR);
+    }
+
+    #[test]
+    fn failed_claim_restores_dividends() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(4 * ONE_NEAR)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.distribute_dividends();
+
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.claim_dividends();
+        assert_eq!(contract.withdrawable_dividend_of(accounts(1)).0, 0);
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        assert!(!contract.on_dividends_claimed(accounts(1), ONE_NEAR.into()));
+        assert_eq!(contract.withdrawable_dividend_of(accounts(1)).0, ONE_NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "more of available storage balance")]
+    fn settle_without_storage_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(ONE_NEAR)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.distribute_dividends();
+
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(4), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "No dividends to claim")]
+    fn claim_dividends_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.claim_dividends();
+    }
+}
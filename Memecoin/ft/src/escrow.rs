@@ -0,0 +1,343 @@
+/*!
+Escrowed payments with an arbiter.
+
+`create_escrow` locks tokens of the payer for a payee, with a third account as the arbiter. The
+escrow is settled once, depending on who calls:
+
+- the payer can `release_escrow` to pay the payee,
+- the payee can `refund_escrow` to return the tokens to the payer, and so can the payer once
+  the deadline has passed,
+- the arbiter can `arbitrate_escrow` to give `split_bps` of the tokens to the payee and the rest
+  to the payer.
+
+Settling fails if an account that gets a share of the tokens is frozen. An account can be a party
+to up to 100 open escrows.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::U64;
+
+const DEFAULT_ESCROWS_LIMIT: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Escrow {
+    payer_id: AccountId,
+    payee_id: AccountId,
+    arbiter_id: AccountId,
+    amount: Balance,
+    deadline: u64,
+    storage_cost: Balance,
+}
+
+#[derive(Serialize)]
+pub struct EscrowView {
+    escrow_id: u64,
+    payer_id: AccountId,
+    payee_id: AccountId,
+    arbiter_id: AccountId,
+    amount: U128,
+    deadline: U64,
+}
+
+#[derive(Serialize)]
+struct EscrowCreated<'a> {
+    escrow_id: u64,
+    payer_id: &'a AccountId,
+    payee_id: &'a AccountId,
+    arbiter_id: &'a AccountId,
+    amount: U128,
+    deadline: U64,
+}
+
+#[derive(Serialize)]
+struct EscrowSettled<'a> {
+    escrow_id: u64,
+    settled_by: &'a AccountId,
+    payee_amount: U128,
+    payer_amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` tokens of the caller for `payee_id`, and returns the id of the escrow.
+    #[payable]
+    pub fn create_escrow(
+        &mut self,
+        payee_id: AccountId,
+        arbiter_id: AccountId,
+        amount: U128,
+        deadline: U64,
+    ) -> u64 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let payer_id = env::predecessor_account_id();
+        self.assert_not_frozen(&payer_id, &payee_id);
+        self.assert_no_transfer_policy();
+        require!(payer_id != payee_id, "Payer and payee should be different");
+        require!(
+            arbiter_id != payer_id && arbiter_id != payee_id,
+            "The arbiter must be a third party"
+        );
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(deadline.0 > env::block_timestamp(), "The deadline must be in the future");
+        let initial_storage_usage = env::storage_usage();
+        self.internal_lock_escrow(Lock::Escrow, &payer_id, amount.0, "escrow");
+
+        let escrow_id = self.next_escrow_id;
+        self.next_escrow_id += 1;
+        let mut escrow = Escrow {
+            payer_id: payer_id.clone(),
+            payee_id: payee_id.clone(),
+            arbiter_id: arbiter_id.clone(),
+            amount: amount.0,
+            deadline: deadline.0,
+            storage_cost: 0,
+        };
+        self.escrows.insert(&escrow_id, &escrow);
+        push_account_ids(
+            &mut self.account_escrows,
+            &[&payer_id, &payee_id, &arbiter_id],
+            escrow_id,
+        );
+        escrow.storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        self.escrows.insert(&escrow_id, &escrow);
+
+        emit_nep297_event(
+            "escrow_created",
+            &[EscrowCreated {
+                escrow_id,
+                payer_id: &payer_id,
+                payee_id: &payee_id,
+                arbiter_id: &arbiter_id,
+                amount,
+                deadline,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        escrow_id
+    }
+
+    /// Pays the escrowed tokens to the payee. Only the payer can release.
+    #[payable]
+    pub fn release_escrow(&mut self, escrow_id: u64) {
+        assert_one_yocto();
+        let escrow = self.internal_unwrap_escrow(escrow_id);
+        require!(
+            env::predecessor_account_id() == escrow.payer_id,
+            "Only the payer can release the escrow"
+        );
+        let amount = escrow.amount;
+        self.internal_settle_escrow(escrow_id, escrow, amount, "escrow_released");
+    }
+
+    /// Returns the escrowed tokens to the payer. The payee can refund at any time, and the
+    /// payer once the deadline has passed.
+    #[payable]
+    pub fn refund_escrow(&mut self, escrow_id: u64) {
+        assert_one_yocto();
+        let escrow = self.internal_unwrap_escrow(escrow_id);
+        let caller_id = env::predecessor_account_id();
+        require!(
+            caller_id == escrow.payee_id
+                || (caller_id == escrow.payer_id && escrow.deadline <= env::block_timestamp()),
+            "Only the payee can refund the escrow before the deadline"
+        );
+        self.internal_settle_escrow(escrow_id, escrow, 0, "escrow_refunded");
+    }
+
+    /// Gives `split_bps` of the escrowed tokens to the payee and the rest to the payer. Only the
+    /// arbiter can arbitrate.
+    #[payable]
+    pub fn arbitrate_escrow(&mut self, escrow_id: u64, split_bps: u16) {
+        assert_one_yocto();
+        let escrow = self.internal_unwrap_escrow(escrow_id);
+        require!(
+            env::predecessor_account_id() == escrow.arbiter_id,
+            "Only the arbiter can arbitrate the escrow"
+        );
+        require!(split_bps <= MAX_BPS, "Split must be at most 10000 basis points");
+        let payee_amount = mul_div(escrow.amount, split_bps.into(), MAX_BPS.into());
+        self.internal_settle_escrow(escrow_id, escrow, payee_amount, "escrow_arbitrated");
+    }
+
+    pub fn get_escrow(&self, escrow_id: u64) -> Option<EscrowView> {
+        self.escrows.get(&escrow_id).map(|escrow| escrow_view(escrow_id, escrow))
+    }
+
+    /// Returns the open escrows where `account_id` is the payer, the payee or the arbiter, oldest
+    /// first.
+    pub fn get_escrows(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<EscrowView> {
+        let limit = limit.unwrap_or(DEFAULT_ESCROWS_LIMIT);
+        account_ids_page(&self.account_escrows, &account_id, from_index, limit)
+            .into_iter()
+            .map(|escrow_id| escrow_view(escrow_id, self.escrows.get(&escrow_id).unwrap()))
+            .collect()
+    }
+}
+
+fn escrow_view(escrow_id: u64, escrow: Escrow) -> EscrowView {
+    EscrowView {
+        escrow_id,
+        payer_id: escrow.payer_id,
+        payee_id: escrow.payee_id,
+        arbiter_id: escrow.arbiter_id,
+        amount: escrow.amount.into(),
+        deadline: escrow.deadline.into(),
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_escrow(&self, escrow_id: u64) -> Escrow {
+        self.escrows.get(&escrow_id).unwrap_or_else(|| env::panic_str("Escrow not found"))
+    }
+
+    /// Removes the escrow, pays `payee_amount` to the payee and the rest to the payer, and
+    /// refunds the storage of the escrow to the payer.
+    fn internal_settle_escrow(
+        &mut self,
+        escrow_id: u64,
+        escrow: Escrow,
+        payee_amount: Balance,
+        event: &str,
+    ) {
+        let payer_amount = escrow.amount - payee_amount;
+        if payee_amount > 0 {
+            self.assert_receiver_not_frozen(&escrow.payee_id);
+        }
+        if payer_amount > 0 {
+            self.assert_receiver_not_frozen(&escrow.payer_id);
+        }
+        self.escrows.remove(&escrow_id);
+        remove_account_ids(
+            &mut self.account_escrows,
+            &[&escrow.payer_id, &escrow.payee_id, &escrow.arbiter_id],
+            escrow_id,
+        );
+
+        if payee_amount > 0 {
+            self.internal_release_escrow(
+                Lock::Escrow,
+                &escrow.payee_id,
+                payee_amount,
+                Some("escrow".to_string()),
+            );
+        }
+        if payer_amount > 0 {
+            self.internal_release_escrow(
+                Lock::Escrow,
+                &escrow.payer_id,
+                payer_amount,
+                Some("escrow refunded".to_string()),
+            );
+        }
+        emit_nep297_event(
+            event,
+            &[EscrowSettled {
+                escrow_id,
+                settled_by: &env::predecessor_account_id(),
+                payee_amount: payee_amount.into(),
+                payer_amount: payer_amount.into(),
+            }],
+        );
+        if escrow.storage_cost > 0 {
+            Promise::new(escrow.payer_id).transfer(escrow.storage_cost);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    const SECOND: u64 = 1_000_000_000;
+    const AMOUNT: Balance = 1_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        contract.create_escrow(accounts(1), accounts(3), AMOUNT.into(), U64(100 * SECOND));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn release_escrow() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - AMOUNT);
+        for account_id in [accounts(1), accounts(2), accounts(3)].iter() {
+            assert_eq!(contract.get_escrows(account_id.clone(), None, None)[0].escrow_id, 0);
+        }
+        contract.release_escrow(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, AMOUNT);
+        assert!(contract.get_escrow(0).is_none());
+        assert!(contract.get_escrows(accounts(3), None, None).is_empty());
+    }
+
+    #[test]
+    fn arbitrate_escrow() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.arbitrate_escrow(0, 7_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, AMOUNT * 7 / 10);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - AMOUNT * 7 / 10);
+    }
+
+    #[test]
+    fn refund_escrow_after_deadline() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(100 * SECOND).build());
+        contract.refund_escrow(0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    fn refund_escrow_to_frozen_payee() {
+        let (mut context, mut contract) = setup();
+        contract.update_use_freeze(true);
+        contract.freeze_account(accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.refund_escrow(0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver account is frozen")]
+    fn release_escrow_to_frozen_payee_fail() {
+        let (_, mut contract) = setup();
+        contract.update_use_freeze(true);
+        contract.freeze_account(accounts(1));
+        contract.release_escrow(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the payee can refund the escrow before the deadline")]
+    fn refund_escrow_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(100 * SECOND - 1).build());
+        contract.refund_escrow(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "is already a party to 100 open entries")]
+    fn too_many_escrows_fail() {
+        let (mut context, mut contract) = setup();
+        for _ in 0..MAX_ACCOUNT_IDS {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(DEPOSIT)
+                .build());
+            contract.create_escrow(accounts(1), accounts(3), AMOUNT.into(), U64(100 * SECOND));
+        }
+    }
+}
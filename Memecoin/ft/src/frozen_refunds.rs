@@ -0,0 +1,333 @@
+/*!
+Freeze-aware refunds of `ft_transfer_call`.
+
+The sender of an `ft_transfer_call` can be frozen while the transfer is in flight. When the
+transfer resolves, the unused tokens then go to the frozen refund escrow instead of the sender,
+and the owner can settle them from there. The `ft_resolve_transfer` result counts the whole
+amount as used, since the sender got nothing back.
+
+The receiver can be frozen while the transfer is in flight as well. Its unused tokens are still
+refunded, since they leave its balance without it sending them, and the refund is logged with a
+`frozen_receiver_refunded` event so that the owner can see what a frozen account gave back. When
+both are frozen, the refund goes to the escrow.
+
+The escrow is the owner account unless the owner sets another one. While the escrow is not
+registered, the unused tokens are refunded to the frozen sender as usual, where they stay frozen.
+A policy-checked transfer that is approved while its sender or receiver is frozen goes to the
+escrow as well, and so does one that is rejected while its sender is frozen.
+*/
+use crate::*;
+use sink::unused_transfer_amount;
+
+#[derive(Serialize)]
+struct FrozenRefundEscrowSet<'a> {
+    escrow_id: &'a AccountId,
+}
+
+#[derive(Serialize)]
+struct FrozenReceiverRefunded<'a> {
+    sender_id: &'a AccountId,
+    receiver_id: &'a AccountId,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+struct FrozenRefundEscrowed<'a> {
+    sender_id: &'a AccountId,
+    receiver_id: &'a AccountId,
+    escrow_id: &'a AccountId,
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that gets the refunds of frozen senders, or resets it to the owner.
+    #[payable]
+    pub fn set_frozen_refund_escrow(&mut self, escrow_id: Option<AccountId>) {
+        self.only_owner();
+        assert_one_yocto();
+        if let Some(escrow_id) = &escrow_id {
+            require!(
+                self.token.accounts.contains_key(escrow_id),
+                "The escrow account is not registered"
+            );
+        }
+        self.frozen_refund_escrow = escrow_id;
+        emit_nep297_event(
+            "frozen_refund_escrow_set",
+            &[FrozenRefundEscrowSet { escrow_id: &self.get_frozen_refund_escrow() }],
+        );
+    }
+
+    pub fn get_frozen_refund_escrow(&self) -> AccountId {
+        self.frozen_refund_escrow.clone().unwrap_or_else(|| self.owner_id.clone())
+    }
+}
+
+impl Contract {
+    /// Resolves a transfer from or to a frozen account, and returns the used amount like
+    /// `internal_ft_resolve_transfer`. The unused tokens of a frozen sender go to the frozen
+    /// refund escrow, and those of a frozen receiver back to the sender. Returns `None` if neither
+    /// is frozen, or if the escrow or the sender that should get the refund is not registered.
+    pub(crate) fn internal_resolve_frozen_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Option<Balance> {
+        let sender_frozen = self.internal_is_frozen(sender_id);
+        if !sender_frozen && !self.internal_is_frozen(receiver_id) {
+            return None;
+        }
+        let refund_amount =
+            unused_transfer_amount(amount).min(self.token.accounts.get(receiver_id).unwrap_or(0));
+        if !sender_frozen {
+            if !self.internal_refund_frozen_receiver(sender_id, receiver_id, refund_amount) {
+                return None;
+            }
+            return Some(amount - refund_amount);
+        }
+        if refund_amount > 0
+            && !self.internal_escrow_frozen_refund(
+                receiver_id,
+                sender_id,
+                receiver_id,
+                refund_amount,
+            )
+        {
+            return None;
+        }
+        Some(amount)
+    }
+
+    /// Moves the refund of a frozen `receiver_id` back to `sender_id`. Returns false, without
+    /// moving anything, if the sender is not registered.
+    fn internal_refund_frozen_receiver(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        if !self.token.accounts.contains_key(sender_id) {
+            return false;
+        }
+        if amount > 0 {
+            self.internal_transfer(
+                receiver_id,
+                sender_id,
+                amount,
+                Some("frozen receiver refund".to_string()),
+            );
+            emit_nep297_event(
+                "frozen_receiver_refunded",
+                &[FrozenReceiverRefunded { sender_id, receiver_id, amount: amount.into() }],
+            );
+        }
+        true
+    }
+
+    /// Moves the refund of `sender_id` for a transfer to `receiver_id` from `holder_id` to the
+    /// frozen refund escrow. Returns false, without moving anything, if the escrow is not
+    /// registered.
+    pub(crate) fn internal_escrow_frozen_refund(
+        &mut self,
+        holder_id: &AccountId,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        let escrow_id = self.get_frozen_refund_escrow();
+        if !self.token.accounts.contains_key(&escrow_id) {
+            return false;
+        }
+        if escrow_id != *holder_id {
+            self.internal_transfer(
+                holder_id,
+                &escrow_id,
+                amount,
+                Some("frozen refund".to_string()),
+            );
+        }
+        emit_nep297_event(
+            "frozen_refund_escrowed",
+            &[FrozenRefundEscrowed {
+                sender_id,
+                receiver_id,
+                escrow_id: &escrow_id,
+                amount: amount.into(),
+            }],
+        );
+        true
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::mock::{Receipt, VmAction};
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig, ONE_YOCTO};
+    use serde::de::DeserializeOwned;
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::tests::setup_with;
+
+    const AMOUNT: Balance = 1_000;
+
+    /// A receiver that keeps `used` of the tokens it gets and returns the rest.
+    struct MockReceiver {
+        used: Balance,
+    }
+
+    impl FungibleTokenReceiver for MockReceiver {
+        fn ft_on_transfer(
+            &mut self,
+            _sender_id: AccountId,
+            amount: U128,
+            _msg: String,
+        ) -> PromiseOrValue<U128> {
+            PromiseOrValue::Value(U128(amount.0 - self.used.min(amount.0)))
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct OnTransferArgs {
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    }
+
+    #[derive(Deserialize)]
+    struct ResolveTransferArgs {
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    }
+
+    /// Returns the arguments of the `method_name` call among `receipts`.
+    fn call_args<T: DeserializeOwned>(receipts: &[Receipt], method_name: &str) -> T {
+        receipts
+            .iter()
+            .flat_map(|receipt| &receipt.actions)
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, args, .. }
+                    if function_name == method_name =>
+                {
+                    Some(serde_json::from_slice(args).unwrap())
+                }
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    /// Starts an `ft_transfer_call` of `AMOUNT` from accounts(1) to accounts(3), and returns
+    /// the receipts it created.
+    fn setup() -> (VMContextBuilder, Contract, Vec<Receipt>) {
+        let (mut context, mut contract) =
+            setup_with(&[accounts(1), accounts(3), accounts(4)], ONE_YOCTO);
+        contract.update_use_freeze(true);
+        contract.ft_transfer(accounts(1), AMOUNT.into(), None);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .prepaid_gas(Gas(300_000_000_000_000))
+            .build());
+        contract.ft_transfer_call(accounts(3), AMOUNT.into(), None, String::new());
+        let receipts = get_created_receipts();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        (context, contract, receipts)
+    }
+
+    /// Delivers the transfer to `receiver` and resolves it with what the receiver returned.
+    fn resolve(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        receipts: &[Receipt],
+        receiver: &mut MockReceiver,
+    ) -> Balance {
+        let args: OnTransferArgs = call_args(receipts, "ft_on_transfer");
+        let unused = match receiver.ft_on_transfer(args.sender_id, args.amount, args.msg) {
+            PromiseOrValue::Value(unused) => unused,
+            PromiseOrValue::Promise(_) => unreachable!(),
+        };
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(serde_json::to_vec(&unused).unwrap())],
+        );
+        let args: ResolveTransferArgs = call_args(receipts, "ft_resolve_transfer");
+        contract.ft_resolve_transfer(args.sender_id, args.receiver_id, args.amount).0
+    }
+
+    #[test]
+    fn partial_refund() {
+        let (mut context, mut contract, receipts) = setup();
+        let mut receiver = MockReceiver { used: 600 };
+        assert_eq!(resolve(&mut context, &mut contract, &receipts, &mut receiver), 600);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 600);
+    }
+
+    #[test]
+    fn refund_to_frozen_sender_goes_to_escrow() {
+        let (mut context, mut contract, receipts) = setup();
+        contract.set_frozen_refund_escrow(Some(accounts(4)));
+        contract.freeze_account(accounts(1));
+        let mut receiver = MockReceiver { used: 600 };
+        assert_eq!(resolve(&mut context, &mut contract, &receipts, &mut receiver), AMOUNT);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 600);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 400);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"frozen_refund_escrowed\"")));
+    }
+
+    #[test]
+    fn refund_to_frozen_sender_without_escrow() {
+        let (mut context, mut contract, receipts) = setup();
+        contract.set_frozen_refund_escrow(Some(accounts(4)));
+        contract.freeze_account(accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.storage_unregister(None);
+        let mut receiver = MockReceiver { used: 600 };
+        assert_eq!(resolve(&mut context, &mut contract, &receipts, &mut receiver), 600);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 600);
+    }
+
+    #[test]
+    fn refund_from_frozen_receiver_goes_to_sender() {
+        let (mut context, mut contract, receipts) = setup();
+        contract.freeze_account(accounts(3));
+        let mut receiver = MockReceiver { used: 600 };
+        assert_eq!(resolve(&mut context, &mut contract, &receipts, &mut receiver), 600);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 600);
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("\"event\":\"frozen_receiver_refunded\"")
+                && log.contains("\"amount\":\"400\"")));
+    }
+
+    #[test]
+    fn refund_between_frozen_accounts_goes_to_escrow() {
+        let (mut context, mut contract, receipts) = setup();
+        contract.set_frozen_refund_escrow(Some(accounts(4)));
+        contract.freeze_account(accounts(1));
+        contract.freeze_account(accounts(3));
+        let mut receiver = MockReceiver { used: 600 };
+        assert_eq!(resolve(&mut context, &mut contract, &receipts, &mut receiver), AMOUNT);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 600);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 400);
+    }
+
+    #[test]
+    #[should_panic(expected = "The escrow account is not registered")]
+    fn set_frozen_refund_escrow_fail() {
+        let (_, mut contract, _) = setup();
+        contract.set_frozen_refund_escrow(Some(accounts(5)));
+    }
+}
@@ -0,0 +1,252 @@
+/*!
+Hash time-locked transfers.
+
+`lock_htlc` escrows tokens of the sender for a receiver under a `hashlock`, the `env::sha256`
+hash of a secret preimage. Until `timelock`, anyone who knows the preimage can `redeem_htlc` to
+pay the receiver. The `htlc_redeemed` event reveals the preimage, which lets the counterparty of
+a swap redeem a matching HTLC on another token. From `timelock` on, `refund_htlc` returns the
+tokens to the sender instead.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::{Base64VecU8, U64};
+use std::convert::TryInto;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Htlc {
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    amount: Balance,
+    hashlock: [u8; 32],
+    timelock: u64,
+    storage_cost: Balance,
+}
+
+#[derive(Serialize)]
+pub struct HtlcView {
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    amount: U128,
+    hashlock: Base64VecU8,
+    timelock: U64,
+}
+
+#[derive(Serialize)]
+struct HtlcLocked<'a> {
+    htlc_id: u64,
+    sender_id: &'a AccountId,
+    receiver_id: &'a AccountId,
+    amount: U128,
+    hashlock: &'a Base64VecU8,
+    timelock: U64,
+}
+
+#[derive(Serialize)]
+struct HtlcRedeemed<'a> {
+    htlc_id: u64,
+    receiver_id: &'a AccountId,
+    preimage: &'a Base64VecU8,
+}
+
+#[derive(Serialize)]
+struct HtlcRefunded<'a> {
+    htlc_id: u64,
+    sender_id: &'a AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` tokens of the caller for `receiver_id` until `timelock`, and returns the
+    /// id of the HTLC.
+    #[payable]
+    pub fn lock_htlc(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        hashlock: Base64VecU8,
+        timelock: U64,
+    ) -> u64 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_frozen(&sender_id, &receiver_id);
+        self.assert_no_transfer_policy();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(timelock.0 > env::block_timestamp(), "The timelock must be in the future");
+        let lock: [u8; 32] = hashlock
+            .0
+            .as_slice()
+            .try_into()
+            .unwrap_or_else(|_| env::panic_str("Hashlock must be 32 bytes"));
+        let initial_storage_usage = env::storage_usage();
+        self.internal_lock_escrow(Lock::Htlc, &sender_id, amount.0, "htlc");
+
+        let htlc_id = self.next_htlc_id;
+        self.next_htlc_id += 1;
+        let mut htlc = Htlc {
+            sender_id: sender_id.clone(),
+            receiver_id: receiver_id.clone(),
+            amount: amount.0,
+            hashlock: lock,
+            timelock: timelock.0,
+            storage_cost: 0,
+        };
+        self.htlcs.insert(&htlc_id, &htlc);
+        htlc.storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        self.htlcs.insert(&htlc_id, &htlc);
+
+        emit_nep297_event(
+            "htlc_locked",
+            &[HtlcLocked {
+                htlc_id,
+                sender_id: &sender_id,
+                receiver_id: &receiver_id,
+                amount,
+                hashlock: &hashlock,
+                timelock,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        htlc_id
+    }
+
+    /// Pays the receiver if `preimage` hashes to the hashlock. Anyone can redeem before the
+    /// timelock.
+    pub fn redeem_htlc(&mut self, htlc_id: u64, preimage: Base64VecU8) -> U128 {
+        let htlc = self.internal_unwrap_htlc(htlc_id);
+        self.assert_receiver_not_frozen(&htlc.receiver_id);
+        require!(env::block_timestamp() < htlc.timelock, "The HTLC has expired");
+        require!(env::sha256(&preimage.0) == htlc.hashlock, "Invalid preimage");
+        self.internal_close_htlc(htlc_id, &htlc);
+
+        self.internal_release_escrow(
+            Lock::Htlc,
+            &htlc.receiver_id,
+            htlc.amount,
+            Some("htlc".to_string()),
+        );
+        emit_nep297_event(
+            "htlc_redeemed",
+            &[HtlcRedeemed { htlc_id, receiver_id: &htlc.receiver_id, preimage: &preimage }],
+        );
+        htlc.amount.into()
+    }
+
+    /// Returns the tokens to the sender once the timelock has passed. Anyone can refund.
+    pub fn refund_htlc(&mut self, htlc_id: u64) -> U128 {
+        let htlc = self.internal_unwrap_htlc(htlc_id);
+        require!(htlc.timelock <= env::block_timestamp(), "The HTLC has not expired yet");
+        self.assert_receiver_not_frozen(&htlc.sender_id);
+        self.internal_close_htlc(htlc_id, &htlc);
+
+        self.internal_release_escrow(
+            Lock::Htlc,
+            &htlc.sender_id,
+            htlc.amount,
+            Some("htlc refunded".to_string()),
+        );
+        emit_nep297_event("htlc_refunded", &[HtlcRefunded { htlc_id, sender_id: &htlc.sender_id }]);
+        htlc.amount.into()
+    }
+
+    pub fn get_htlc(&self, htlc_id: u64) -> Option<HtlcView> {
+        self.htlcs.get(&htlc_id).map(|htlc| HtlcView {
+            sender_id: htlc.sender_id,
+            receiver_id: htlc.receiver_id,
+            amount: htlc.amount.into(),
+            hashlock: htlc.hashlock.to_vec().into(),
+            timelock: htlc.timelock.into(),
+        })
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_htlc(&self, htlc_id: u64) -> Htlc {
+        self.htlcs.get(&htlc_id).unwrap_or_else(|| env::panic_str("HTLC not found"))
+    }
+
+    /// Removes the HTLC and refunds its storage to the sender.
+    fn internal_close_htlc(&mut self, htlc_id: u64, htlc: &Htlc) {
+        self.htlcs.remove(&htlc_id);
+        if htlc.storage_cost > 0 {
+            Promise::new(htlc.sender_id.clone()).transfer(htlc.storage_cost);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    const SECOND: u64 = 1_000_000_000;
+    const AMOUNT: Balance = 1_000;
+    const PREIMAGE: &[u8] = b"secret";
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        contract.lock_htlc(
+            accounts(1),
+            AMOUNT.into(),
+            env::sha256(PREIMAGE).into(),
+            U64(100 * SECOND),
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(3))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn redeem_htlc() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - AMOUNT);
+        assert_eq!(contract.redeem_htlc(0, PREIMAGE.to_vec().into()).0, AMOUNT);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, AMOUNT);
+        assert!(get_logs().iter().any(|log| log.contains("\"preimage\":\"c2VjcmV0\"")));
+        assert!(contract.get_htlc(0).is_none());
+    }
+
+    #[test]
+    fn refund_htlc_after_timelock() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(100 * SECOND).build());
+        assert_eq!(contract.refund_htlc(0).0, AMOUNT);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        assert!(contract.get_htlc(0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid preimage")]
+    fn redeem_htlc_fail() {
+        let (_, mut contract) = setup();
+        contract.redeem_htlc(0, b"guess".to_vec().into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The HTLC has not expired yet")]
+    fn refund_htlc_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(100 * SECOND - 1).build());
+        contract.refund_htlc(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Receiver account is frozen")]
+    fn refund_htlc_to_frozen_sender_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.update_use_freeze(true);
+        contract.freeze_account(accounts(2));
+        testing_env!(context.block_timestamp(100 * SECOND).build());
+        contract.refund_htlc(0);
+    }
+}
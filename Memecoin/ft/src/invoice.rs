@@ -0,0 +1,304 @@
+/*!
+Invoice payments.
+
+A merchant issues an invoice with `create_invoice`: an id of its own, the exact amount to pay, the
+time it expires at and optionally the only account that may pay it. The merchant pays for the
+storage of the invoice, and can cancel it with `cancel_invoice` until it is paid. `pay_invoice`
+transfers exactly the invoiced amount to the merchant and records who paid it, how much the
+merchant got and when, so the same invoice can't be paid twice. Invoice ids are scoped to the
+merchant that created them, so nobody else can take them. Merchants match payments to orders with
+the `invoice_paid` event or the `get_invoice_payment` view instead of parsing memos.
+*/
+use crate::*;
+use near_sdk::json_types::U64;
+
+const MAX_INVOICE_ID_LENGTH: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Invoice {
+    amount: Balance,
+    payer_id: Option<AccountId>,
+    expires_at: u64,
+    payment: Option<InvoicePayment>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct InvoicePayment {
+    payer_id: AccountId,
+    amount: Balance,
+    timestamp: u64,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceView {
+    amount: U128,
+    payer_id: Option<AccountId>,
+    expires_at: U64,
+    payment: Option<InvoicePaymentView>,
+}
+
+#[derive(Serialize)]
+pub struct InvoicePaymentView {
+    payer_id: AccountId,
+    amount: U128,
+    timestamp: U64,
+}
+
+impl From<InvoicePayment> for InvoicePaymentView {
+    fn from(payment: InvoicePayment) -> Self {
+        Self {
+            payer_id: payment.payer_id,
+            amount: payment.amount.into(),
+            timestamp: payment.timestamp.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct InvoiceCreated<'a> {
+    merchant_id: &'a AccountId,
+    invoice_id: &'a str,
+    amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payer_id: Option<&'a AccountId>,
+    expires_at: U64,
+}
+
+#[derive(Serialize)]
+struct InvoiceCancelled<'a> {
+    merchant_id: &'a AccountId,
+    invoice_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct InvoicePaid<'a> {
+    merchant_id: &'a AccountId,
+    invoice_id: &'a str,
+    payer_id: &'a AccountId,
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Issues `invoice_id` of the caller for `amount`, payable until `expires_at` by `payer_id`,
+    /// or by anyone without one.
+    #[payable]
+    pub fn create_invoice(
+        &mut self,
+        invoice_id: String,
+        amount: U128,
+        payer_id: Option<AccountId>,
+        expires_at: U64,
+    ) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(
+            !invoice_id.is_empty() && invoice_id.len() <= MAX_INVOICE_ID_LENGTH,
+            "Invoice id must have between 1 and 64 bytes"
+        );
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(expires_at.0 > env::block_timestamp(), "The invoice must expire in the future");
+        let merchant_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let key = (merchant_id.clone(), invoice_id.clone());
+        require!(!self.invoices.contains_key(&key), "The invoice already exists");
+        self.invoices.insert(
+            &key,
+            &Invoice {
+                amount: amount.into(),
+                payer_id: payer_id.clone(),
+                expires_at: expires_at.0,
+                payment: None,
+            },
+        );
+        emit_nep297_event(
+            "invoice_created",
+            &[InvoiceCreated {
+                merchant_id: &merchant_id,
+                invoice_id: &invoice_id,
+                amount,
+                payer_id: payer_id.as_ref(),
+                expires_at,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    /// Removes an unpaid invoice of the caller and refunds its storage.
+    #[payable]
+    pub fn cancel_invoice(&mut self, invoice_id: String) {
+        assert_one_yocto();
+        let merchant_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let key = (merchant_id.clone(), invoice_id.clone());
+        let invoice =
+            self.invoices.remove(&key).unwrap_or_else(|| env::panic_str("Invoice not found"));
+        require!(invoice.payment.is_none(), "The invoice was already paid");
+        emit_nep297_event(
+            "invoice_cancelled",
+            &[InvoiceCancelled { merchant_id: &merchant_id, invoice_id: &invoice_id }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    /// Pays `amount`, which must be the invoiced amount, to `merchant_id` for `invoice_id`, and
+    /// returns the amount the merchant got.
+    #[payable]
+    pub fn pay_invoice(
+        &mut self,
+        merchant_id: AccountId,
+        invoice_id: String,
+        amount: U128,
+    ) -> U128 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let payer_id = env::predecessor_account_id();
+        self.assert_not_frozen(&payer_id, &merchant_id);
+        self.assert_no_transfer_policy();
+        let initial_storage_usage = env::storage_usage();
+        let key = (merchant_id.clone(), invoice_id.clone());
+        let mut invoice =
+            self.invoices.get(&key).unwrap_or_else(|| env::panic_str("Invoice not found"));
+        require!(invoice.payment.is_none(), "The invoice was already paid");
+        require!(env::block_timestamp() < invoice.expires_at, "The invoice has expired");
+        require!(
+            invoice.payer_id.is_none() || invoice.payer_id.as_ref() == Some(&payer_id),
+            "The invoice is for another payer"
+        );
+        require!(amount.0 == invoice.amount, "The amount doesn't match the invoice");
+        let received = self.internal_transfer_with_fees(
+            &payer_id,
+            &merchant_id,
+            amount.into(),
+            Some(format!("invoice {}", invoice_id)),
+        );
+        invoice.payment = Some(InvoicePayment {
+            payer_id: payer_id.clone(),
+            amount: received,
+            timestamp: env::block_timestamp(),
+        });
+        self.invoices.insert(&key, &invoice);
+        emit_nep297_event(
+            "invoice_paid",
+            &[InvoicePaid {
+                merchant_id: &merchant_id,
+                invoice_id: &invoice_id,
+                payer_id: &payer_id,
+                amount: received.into(),
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        received.into()
+    }
+
+    pub fn get_invoice(&self, merchant_id: AccountId, invoice_id: String) -> Option<InvoiceView> {
+        self.invoices.get(&(merchant_id, invoice_id)).map(|invoice| InvoiceView {
+            amount: invoice.amount.into(),
+            payer_id: invoice.payer_id,
+            expires_at: invoice.expires_at.into(),
+            payment: invoice.payment.map(Into::into),
+        })
+    }
+
+    pub fn is_invoice_paid(&self, merchant_id: AccountId, invoice_id: String) -> bool {
+        self.get_invoice_payment(merchant_id, invoice_id).is_some()
+    }
+
+    /// Returns who paid the invoice, how much the merchant got and when, if it was paid.
+    pub fn get_invoice_payment(
+        &self,
+        merchant_id: AccountId,
+        invoice_id: String,
+    ) -> Option<InvoicePaymentView> {
+        self.invoices
+            .get(&(merchant_id, invoice_id))
+            .and_then(|invoice| invoice.payment)
+            .map(Into::into)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT};
+
+    const EXPIRES_AT: u64 = 1_000;
+
+    /// Creates "order-1" of account 1 for 1000 tokens, and leaves the owner as the caller.
+    fn setup(payer_id: Option<AccountId>) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1), accounts(3)], DEPOSIT);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.create_invoice("order-1".to_string(), 1_000.into(), payer_id, EXPIRES_AT.into());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn pay_invoice() {
+        let (_, mut contract) = setup(Some(accounts(2)));
+        assert!(!contract.is_invoice_paid(accounts(1), "order-1".to_string()));
+        assert_eq!(contract.pay_invoice(accounts(1), "order-1".to_string(), 1_000.into()).0, 1_000);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert!(contract.is_invoice_paid(accounts(1), "order-1".to_string()));
+        assert!(!contract.is_invoice_paid(accounts(3), "order-1".to_string()));
+        let payment = contract.get_invoice_payment(accounts(1), "order-1".to_string()).unwrap();
+        assert_eq!(payment.payer_id, accounts(2));
+        assert_eq!(payment.amount.0, 1_000);
+        assert!(contract.get_invoice_payment(accounts(1), "order-2".to_string()).is_none());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"invoice_paid\"")
+            && log.contains("\"invoice_id\":\"order-1\"")));
+    }
+
+    #[test]
+    fn cancel_invoice() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.cancel_invoice("order-1".to_string());
+        assert!(contract.get_invoice(accounts(1), "order-1".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The invoice was already paid")]
+    fn pay_invoice_twice_fail() {
+        let (_, mut contract) = setup(None);
+        contract.pay_invoice(accounts(1), "order-1".to_string(), 1_000.into());
+        contract.pay_invoice(accounts(1), "order-1".to_string(), 1_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invoice not found")]
+    fn pay_unknown_invoice_fail() {
+        let (_, mut contract) = setup(None);
+        contract.pay_invoice(accounts(3), "order-1".to_string(), 1_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount doesn't match the invoice")]
+    fn pay_invoice_amount_fail() {
+        let (_, mut contract) = setup(None);
+        contract.pay_invoice(accounts(1), "order-1".to_string(), 1.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The invoice is for another payer")]
+    fn pay_invoice_payer_fail() {
+        let (_, mut contract) = setup(Some(accounts(3)));
+        contract.pay_invoice(accounts(1), "order-1".to_string(), 1_000.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The invoice has expired")]
+    fn pay_invoice_expired_fail() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context.block_timestamp(EXPIRES_AT).build());
+        contract.pay_invoice(accounts(1), "order-1".to_string(), 1_000.into());
+    }
+}
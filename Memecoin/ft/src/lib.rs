@@ -20,14 +20,120 @@ use near_contract_standards::fungible_token::metadata::{
 };
 use near_contract_standards::fungible_token::FungibleToken;
 use near_contract_standards::fungible_token::core::FungibleTokenCore;
-use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_contract_standards::fungible_token::resolver::{ext_ft_resolver, FungibleTokenResolver};
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet};
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue};
+use near_sdk::{
+    env, log, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PublicKey, StorageUsage,
+};
 use near_sdk::assert_one_yocto;
 use serde::Serialize;
 
+mod accounts;
+mod airdrop;
+mod allowance;
+mod authorization;
+mod burn;
+mod circulating;
+mod claimable;
+mod dividends;
+mod emissions;
+mod escrow;
+mod frozen_refunds;
+mod htlc;
+mod invoice;
+mod migration;
+mod minters;
+mod policy;
+mod reflection;
+mod sink;
+mod snapshot;
+mod split;
+mod sponsorship;
+mod storage;
+mod streaming;
+mod subscriptions;
+mod supply;
+mod vesting;
+mod votes;
+
+#[allow(clippy::all)]
+mod u256 {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+use supply::SupplyChangeKind;
+use u256::U256;
+
+const MAX_BPS: u16 = 10_000;
+
+/// Returns `a * b / c` rounded down, without overflowing on the intermediate product.
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    (U256::from(a) * U256::from(b) / U256::from(c)).as_u128()
+}
+
+/// Open vestings, streams or escrows an account can be a party to, so that nobody can grow the
+/// lists of another account without bound.
+const MAX_ACCOUNT_IDS: usize = 100;
+
+/// Adds `id` to the list of ids of each of `account_ids` in `lists`.
+fn push_account_ids(
+    lists: &mut LookupMap<AccountId, Vec<u64>>,
+    account_ids: &[&AccountId],
+    id: u64,
+) {
+    for account_id in account_ids {
+        let mut ids = lists.get(account_id).unwrap_or_default();
+        if ids.len() >= MAX_ACCOUNT_IDS {
+            env::panic_str(&format!(
+                "The account {} is already a party to {} open entries",
+                account_id, MAX_ACCOUNT_IDS
+            ));
+        }
+        ids.push(id);
+        lists.insert(account_id, &ids);
+    }
+}
+
+/// Removes `id` from the list of ids of each of `account_ids` in `lists`, and the lists that
+/// become empty.
+fn remove_account_ids(
+    lists: &mut LookupMap<AccountId, Vec<u64>>,
+    account_ids: &[&AccountId],
+    id: u64,
+) {
+    for account_id in account_ids {
+        let mut ids = lists.get(account_id).unwrap_or_default();
+        ids.retain(|other_id| *other_id != id);
+        if ids.is_empty() {
+            lists.remove(account_id);
+        } else {
+            lists.insert(account_id, &ids);
+        }
+    }
+}
+
+/// Returns up to `limit` ids from the list of `account_id` in `lists`, starting at `from_index`.
+fn account_ids_page(
+    lists: &LookupMap<AccountId, Vec<u64>>,
+    account_id: &AccountId,
+    from_index: Option<u64>,
+    limit: u64,
+) -> Vec<u64> {
+    let ids = lists.get(account_id).unwrap_or_default();
+    ids.into_iter().skip(from_index.unwrap_or(0) as usize).take(limit as usize).collect()
+}
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
+
 #[derive(Serialize)]
 pub struct LogData {
     event: String,
@@ -49,6 +155,26 @@ fn emit_event(event_name: &str, details: &str) {
     env::log_str(&log_data.to_json_string());
 }
 
+const EVENT_STANDARD: &str = "vrtx-ft";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+struct NearEvent<'a, T: Serialize> {
+    standard: &'static str,
+    version: &'static str,
+    event: &'a str,
+    data: &'a [T],
+}
+
+/// Logs a NEP-297 event for the features this contract adds on top of NEP-141.
+fn emit_nep297_event<T: Serialize>(event: &str, data: &[T]) {
+    let event =
+        NearEvent { standard: EVENT_STANDARD, version: EVENT_STANDARD_VERSION, event, data };
+    #[allow(clippy::redundant_closure)]
+    let json = serde_json::to_string(&event).ok().unwrap_or_else(|| env::abort());
+    env::log_str(&format!("EVENT_JSON:{}", json));
+}
+
 // fn emit_event(event_name: &str, details: &str) {
 //     let log_message = format!("{{\"event\": \"{}\", \"details\": \"{}\"}}", event_name, details);
 //     env::log_str(&log_message);
@@ -63,7 +189,69 @@ pub struct Contract {
     frozen: LookupMap<AccountId, bool>,
     use_mint: bool,
     use_freeze: bool,
-    use_update_metadata: bool
+    use_update_metadata: bool,
+    transfer_policy: Option<AccountId>,
+    allowances: LookupMap<(AccountId, AccountId), allowance::Allowance>,
+    authorization_keys: LookupSet<(AccountId, PublicKey)>,
+    used_authorizations: LookupSet<(AccountId, authorization::AuthorizationKind, u64)>,
+    snapshot_id: u64,
+    account_snapshots: LookupMap<(AccountId, u32), snapshot::Checkpoint>,
+    account_snapshot_counts: LookupMap<AccountId, u32>,
+    total_supply_snapshots: LookupMap<u64, Balance>,
+    delegates: LookupMap<AccountId, AccountId>,
+    vote_checkpoints: LookupMap<(AccountId, u32), votes::VoteCheckpoint>,
+    vote_checkpoint_counts: LookupMap<AccountId, u32>,
+    use_reflection: bool,
+    reflection_fee_bps: u16,
+    reflection_per_token: u128,
+    reflection_pool: Balance,
+    reflection_excluded_supply: Balance,
+    reflection_debts: LookupMap<AccountId, u128>,
+    reflection_excluded: LookupSet<AccountId>,
+    dividends_per_share: u128,
+    dividend_accounts: LookupMap<AccountId, dividends::DividendAccount>,
+    use_burn_on_transfer: bool,
+    burn_on_transfer_bps: u16,
+    burn_floor_supply: Balance,
+    emission_schedule: Option<emissions::EmissionSchedule>,
+    emission_start: u64,
+    emission_last_mint: u64,
+    minters: LookupMap<AccountId, minters::Minter>,
+    claimable_balances: LookupMap<AccountId, Vec<claimable::ClaimableBalance>>,
+    min_claimable_amount: Balance,
+    sponsorship_pool: Balance,
+    sponsors: LookupMap<AccountId, sponsorship::Sponsor>,
+    sponsored_accounts: LookupMap<AccountId, sponsorship::SponsoredAccount>,
+    next_airdrop_id: u64,
+    airdrops: LookupMap<u64, airdrop::Airdrop>,
+    airdrop_claims: LookupMap<(u64, u32), u128>,
+    next_vesting_id: u64,
+    vestings: LookupMap<u64, vesting::Vesting>,
+    account_vestings: LookupMap<AccountId, Vec<u64>>,
+    vesting_balances: LookupMap<AccountId, Balance>,
+    next_stream_id: u64,
+    streams: LookupMap<u64, streaming::Stream>,
+    account_streams: LookupMap<AccountId, Vec<u64>>,
+    next_htlc_id: u64,
+    htlcs: LookupMap<u64, htlc::Htlc>,
+    next_escrow_id: u64,
+    escrows: LookupMap<u64, escrow::Escrow>,
+    account_escrows: LookupMap<AccountId, Vec<u64>>,
+    invoices: LookupMap<(AccountId, String), invoice::Invoice>,
+    next_subscription_id: u64,
+    subscriptions: LookupMap<u64, subscriptions::Subscription>,
+    excluded_accounts: Vec<circulating::ExcludedAccount>,
+    locked_supply: circulating::LockedSupply,
+    total_minted: Balance,
+    total_burned: Balance,
+    supply_changes_count: u64,
+    supply_history: LookupMap<u64, supply::SupplyChange>,
+    sink_account: Option<AccountId>,
+    frozen_refund_escrow: Option<AccountId>,
+    available_storage: LookupMap<AccountId, Balance>,
+    /// Storage added in the current call that was charged to available storage balances.
+    #[borsh_skip]
+    charged_storage_usage: StorageUsage,
 }
 
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -113,17 +301,17 @@ impl Contract {
         assert!(!env::state_exists(), "Already initialized");
         assert!(env::is_valid_account_id(owner_id.as_bytes()), "Initial Owner ID is invalid");
         metadata.assert_valid();
-        let mut this = Self {
-            token: FungibleToken::new(b"a".to_vec()),
-            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-            owner_id: owner_id.clone(),
-            frozen: LookupMap::new(b"f".to_vec()),
-            use_mint,
-            use_freeze,
-            use_update_metadata
-        };
+        let mut this = Self::with_token(
+            FungibleToken::new(b"a".to_vec()),
+            LazyOption::new(b"m".to_vec(), Some(&metadata)),
+            owner_id.clone(),
+        );
+        this.use_mint = use_mint;
+        this.use_freeze = use_freeze;
+        this.use_update_metadata = use_update_metadata;
         this.token.internal_register_account(&owner_id);
         this.token.internal_deposit(&owner_id, total_supply.into());
+        this.internal_record_supply_change(SupplyChangeKind::Mint, &owner_id, total_supply.into());
         near_contract_standards::fungible_token::events::FtMint {
             owner_id: &owner_id,
             amount: &total_supply,
@@ -134,10 +322,22 @@ impl Contract {
     }
 
     fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
+        self.internal_snapshot_balance(&account_id, balance);
+        self.internal_remove_reflection_debt(&account_id);
+        self.internal_settle_dividends(&account_id, balance);
+        self.after_balance_change(Some(&account_id), None, balance);
+        match self.internal_sink_account(&account_id) {
+            Some(sink_id) if balance > 0 => {
+                self.internal_credit_sink(&account_id, &sink_id, balance, "account closed")
+            }
+            _ => self.internal_record_supply_change(SupplyChangeKind::Burn, &account_id, balance),
+        }
+        self.internal_refund_available_storage(&account_id);
         log!("Closed @{} with {}", account_id, balance);
     }
 
     fn on_tokens_burned(&mut self, account_id: AccountId, amount: Balance) {
+        self.internal_record_supply_change(SupplyChangeKind::Burn, &account_id, amount);
         log!("Account @{} burned {}", account_id, amount);
     }
 
@@ -185,12 +385,16 @@ impl Contract {
     #[payable]
     pub fn mint(&mut self, amount: U128) {
         assert!(self.use_mint, "Can not mint more");
-        // 계약의 소유자만이 민팅을 할 수 있습니다.
-        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the contract owner can mint tokens");
+        // 계약의 소유자 외에는 할당량이 남은 minter만 민팅을 할 수 있습니다.
+        let minter_id = env::predecessor_account_id();
+        if minter_id != self.owner_id {
+            self.internal_use_mint_quota(&minter_id, amount.into());
+        }
         assert_one_yocto();
-        self.token.internal_deposit(&self.owner_id, amount.into());
+        self.internal_deposit(&minter_id, amount.into());
+        self.internal_record_supply_change(SupplyChangeKind::Mint, &minter_id, amount.into());
         near_contract_standards::fungible_token::events::FtMint {
-            owner_id: &self.owner_id,
+            owner_id: &minter_id,
             amount: &amount,
             memo: Some("Minting tokens"),
         }
@@ -216,8 +420,7 @@ impl Contract {
 
     // 계정이 프리즈 상태인지 확인합니다.
     pub fn is_frozen(&self, account_id: &AccountId) -> bool {
-        assert!(self.use_freeze, "Can not access");
-        self.frozen.get(account_id).unwrap_or(false)
+        self.internal_is_frozen(account_id)
     }
     
     // 메타데이터 업데이트 기능을 추가합니다.
@@ -231,7 +434,314 @@ impl Contract {
     }
 }
 
-near_contract_standards::impl_fungible_token_storage!(Contract, token, on_account_closed);
+impl Contract {
+    /// Returns a contract around `token` and `metadata`, with everything else empty.
+    fn with_token(
+        token: FungibleToken,
+        metadata: LazyOption<FungibleTokenMetadata>,
+        owner_id: AccountId,
+    ) -> Self {
+        // One whole token.
+        let min_claimable_amount = metadata.get().map_or(0, |metadata| {
+            10u128.checked_pow(metadata.decimals.into()).unwrap_or(Balance::MAX)
+        });
+        Self {
+            token,
+            metadata,
+            owner_id,
+            frozen: LookupMap::new(b"f".to_vec()),
+            use_mint: false,
+            use_freeze: false,
+            use_update_metadata: false,
+            transfer_policy: None,
+            allowances: LookupMap::new(b"p".to_vec()),
+            authorization_keys: LookupSet::new(b"k".to_vec()),
+            used_authorizations: LookupSet::new(b"n".to_vec()),
+            snapshot_id: 0,
+            account_snapshots: LookupMap::new(b"s".to_vec()),
+            account_snapshot_counts: LookupMap::new(b"C".to_vec()),
+            total_supply_snapshots: LookupMap::new(b"t".to_vec()),
+            delegates: LookupMap::new(b"d".to_vec()),
+            vote_checkpoints: LookupMap::new(b"v".to_vec()),
+            vote_checkpoint_counts: LookupMap::new(b"V".to_vec()),
+            use_reflection: false,
+            reflection_fee_bps: 0,
+            reflection_per_token: 0,
+            reflection_pool: 0,
+            reflection_excluded_supply: 0,
+            reflection_debts: LookupMap::new(b"r".to_vec()),
+            reflection_excluded: LookupSet::new(b"x".to_vec()),
+            dividends_per_share: 0,
+            dividend_accounts: LookupMap::new(b"i".to_vec()),
+            use_burn_on_transfer: false,
+            burn_on_transfer_bps: 0,
+            burn_floor_supply: 0,
+            emission_schedule: None,
+            emission_start: 0,
+            emission_last_mint: 0,
+            minters: LookupMap::new(b"q".to_vec()),
+            claimable_balances: LookupMap::new(b"c".to_vec()),
+            min_claimable_amount,
+            sponsorship_pool: 0,
+            sponsors: LookupMap::new(b"o".to_vec()),
+            sponsored_accounts: LookupMap::new(b"g".to_vec()),
+            next_airdrop_id: 0,
+            airdrops: LookupMap::new(b"e".to_vec()),
+            airdrop_claims: LookupMap::new(b"h".to_vec()),
+            next_vesting_id: 0,
+            vestings: LookupMap::new(b"j".to_vec()),
+            account_vestings: LookupMap::new(b"G".to_vec()),
+            vesting_balances: LookupMap::new(b"B".to_vec()),
+            next_stream_id: 0,
+            streams: LookupMap::new(b"l".to_vec()),
+            account_streams: LookupMap::new(b"u".to_vec()),
+            next_htlc_id: 0,
+            htlcs: LookupMap::new(b"w".to_vec()),
+            next_escrow_id: 0,
+            escrows: LookupMap::new(b"y".to_vec()),
+            account_escrows: LookupMap::new(b"z".to_vec()),
+            invoices: LookupMap::new(b"b".to_vec()),
+            next_subscription_id: 0,
+            subscriptions: LookupMap::new(b"S".to_vec()),
+            excluded_accounts: Vec::new(),
+            locked_supply: Default::default(),
+            total_minted: 0,
+            total_burned: 0,
+            supply_changes_count: 0,
+            supply_history: LookupMap::new(b"H".to_vec()),
+            sink_account: None,
+            frozen_refund_escrow: None,
+            available_storage: LookupMap::new(b"A".to_vec()),
+            charged_storage_usage: 0,
+        }
+    }
+
+    /// Ledger changes go through these wrappers instead of `token` directly, so that the
+    /// bookkeeping derived from balances stays in sync with them.
+    pub(crate) fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.before_balance_change(sender_id);
+        self.before_balance_change(receiver_id);
+        self.token.internal_transfer(sender_id, receiver_id, amount, memo);
+        self.after_balance_change(Some(sender_id), Some(receiver_id), amount);
+    }
+
+    /// Same as `internal_transfer` without the `ft_transfer` event, for callers that emit the
+    /// events of several transfers at once.
+    pub(crate) fn internal_transfer_without_event(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.before_balance_change(sender_id);
+        self.before_balance_change(receiver_id);
+        self.token.internal_withdraw(sender_id, amount);
+        self.token.internal_deposit(receiver_id, amount);
+        self.after_balance_change(Some(sender_id), Some(receiver_id), amount);
+    }
+
+    /// Transfers `amount` out of `sender_id` as a user transfer, which pays the transfer fees,
+    /// and returns the amount `receiver_id` got.
+    pub(crate) fn internal_transfer_with_fees(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> Balance {
+        let reflection_fee = self.internal_reflection_fee(amount);
+        let burn_amount = self.internal_transfer_burn_amount(amount);
+        let received = amount - reflection_fee - burn_amount;
+        self.internal_transfer(sender_id, receiver_id, received, memo);
+        self.internal_charge_transfer_fees(sender_id, burn_amount, reflection_fee);
+        received
+    }
+
+    /// Takes the fees of a user transfer from `sender_id`, once the transfer itself is done.
+    pub(crate) fn internal_charge_transfer_fees(
+        &mut self,
+        sender_id: &AccountId,
+        burn_amount: Balance,
+        reflection_fee: Balance,
+    ) {
+        if burn_amount > 0 {
+            self.internal_burn_on_transfer(sender_id, burn_amount);
+        }
+        if reflection_fee > 0 {
+            self.internal_reflect(sender_id, reflection_fee);
+        }
+    }
+
+    pub(crate) fn internal_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        self.before_balance_change(account_id);
+        self.token.internal_deposit(account_id, amount);
+        self.after_balance_change(None, Some(account_id), amount);
+    }
+
+    pub(crate) fn internal_withdraw(&mut self, account_id: &AccountId, amount: Balance) {
+        self.before_balance_change(account_id);
+        self.token.internal_withdraw(account_id, amount);
+        self.after_balance_change(Some(account_id), None, amount);
+    }
+
+    fn before_balance_change(&mut self, account_id: &AccountId) {
+        self.internal_settle_reflection(account_id);
+        let balance = self.token.accounts.get(account_id).unwrap_or(0);
+        self.internal_snapshot_balance(account_id, balance);
+        self.internal_settle_dividends(account_id, balance);
+    }
+
+    /// `amount` moved from `from` to `to`, where `None` stands for minted or burned tokens.
+    fn after_balance_change(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        self.internal_move_delegate_votes(from, to, amount);
+        self.internal_track_reflection_excluded(from, to, amount);
+    }
+
+    /// Calls `ft_on_transfer` on the receiver and resolves the transfer afterwards, once the
+    /// tokens of an `ft_transfer_call` have moved.
+    pub(crate) fn internal_notify_receiver(
+        &self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> Promise {
+        ext_ft_receiver::ext(receiver_id.clone())
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                ext_ft_resolver::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .with_unused_gas_weight(0)
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+    }
+
+    pub(crate) fn internal_is_frozen(&self, account_id: &AccountId) -> bool {
+        self.use_freeze && self.frozen.get(account_id).unwrap_or(false)
+    }
+
+    pub(crate) fn assert_not_frozen(&self, sender_id: &AccountId, receiver_id: &AccountId) {
+        // 계정이 프리즈 상태인지 확인
+        assert!(!self.internal_is_frozen(sender_id), "Sender account is frozen");
+        assert!(!self.internal_is_frozen(receiver_id), "Receiver account is frozen");
+    }
+
+    /// Checks an account that gets escrowed tokens back or paid out, without sending any.
+    pub(crate) fn assert_receiver_not_frozen(&self, receiver_id: &AccountId) {
+        assert!(!self.internal_is_frozen(receiver_id), "Receiver account is frozen");
+    }
+
+    /// Charges the predecessor for the storage added since `initial_storage_usage` out of the
+    /// attached deposit, and transfers it what is left, with the cost of any released storage.
+    pub(crate) fn internal_settle_storage(&self, initial_storage_usage: StorageUsage) {
+        let attached_deposit = env::attached_deposit();
+        // Records charged to the accounts they are about are not charged again.
+        let storage_usage = env::storage_usage() - self.charged_storage_usage;
+        let refund = if storage_usage >= initial_storage_usage {
+            let cost =
+                Balance::from(storage_usage - initial_storage_usage) * env::storage_byte_cost();
+            assert!(attached_deposit >= cost, "Must attach {} yoctoNEAR to cover storage", cost);
+            attached_deposit - cost
+        } else {
+            attached_deposit
+                + Balance::from(initial_storage_usage - storage_usage) * env::storage_byte_cost()
+        };
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    /// Moves `amount` from `account_id` to the contract account, which holds it locked in `lock`
+    /// until it is released with `internal_release_escrow`.
+    pub(crate) fn internal_lock_escrow(
+        &mut self,
+        lock: circulating::Lock,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        let contract_id = env::current_account_id();
+        if !self.token.accounts.contains_key(&contract_id) {
+            self.token.internal_register_account(&contract_id);
+        }
+        self.internal_transfer(account_id, &contract_id, amount, Some(memo.to_string()));
+        self.internal_lock(lock, amount);
+    }
+
+    /// Pays `amount` locked in `lock` by the contract account out to `receiver_id`.
+    pub(crate) fn internal_release_escrow(
+        &mut self,
+        lock: circulating::Lock,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.internal_unlock(lock, amount);
+        self.internal_transfer(&env::current_account_id(), receiver_id, amount, memo);
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        if registration_only == Some(true) {
+            return self.token.storage_deposit(Some(account_id), registration_only);
+        }
+        self.internal_storage_deposit(account_id)
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.internal_storage_withdraw(amount)
+    }
+
+    /// Same as `impl_fungible_token_storage!`, except that the storage deposit of a sponsored
+    /// account goes back to the sponsorship pool.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        // The balance that is burned or sent to the sink includes the pending reflections.
+        self.internal_settle_reflection(&env::predecessor_account_id());
+        let closed = match self.sponsored_accounts.remove(&env::predecessor_account_id()) {
+            Some(sponsored) => self.internal_unregister_sponsored(sponsored, force),
+            None => self.token.internal_storage_unregister(force),
+        };
+        if let Some((account_id, balance)) = closed {
+            self.on_account_closed(account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        let available = self.internal_available_storage(&account_id);
+        self.token.storage_balance_of(account_id).map(|storage_balance| StorageBalance {
+            total: (storage_balance.total.0 + available).into(),
+            available: available.into(),
+        })
+    }
+}
 
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
@@ -250,14 +760,11 @@ impl FungibleTokenCore for Contract {
         memo: Option<String>,
     ) {
         let sender_id = env::predecessor_account_id();
-        
-        // 계정이 프리즈 상태인지 확인
-        if self.use_freeze {
-            assert!(!self.is_frozen(&sender_id), "Sender account is frozen");
-            assert!(!self.is_frozen(&receiver_id), "Receiver account is frozen");
-        }
+        self.assert_not_frozen(&sender_id, &receiver_id);
+        self.assert_no_transfer_policy();
 
-        self.token.ft_transfer(receiver_id, amount, memo)
+        assert_one_yocto();
+        self.internal_transfer_with_fees(&sender_id, &receiver_id, amount.into(), memo);
     }
 
     #[payable]
@@ -269,23 +776,36 @@ impl FungibleTokenCore for Contract {
         msg: String,
     ) -> PromiseOrValue<U128> {
         let sender_id = env::predecessor_account_id();
-
-        // 계정이 프리즈 상태인지 확인
-        if self.use_freeze {
-            assert!(!self.is_frozen(&sender_id), "Sender account is frozen");
-            assert!(!self.is_frozen(&receiver_id), "Receiver account is frozen");
+        self.assert_not_frozen(&sender_id, &receiver_id);
+
+        if let Some(policy_id) = self.transfer_policy.clone() {
+            assert_one_yocto();
+            return self
+                .internal_transfer_with_policy(
+                    policy_id,
+                    sender_id,
+                    receiver_id,
+                    amount.into(),
+                    memo,
+                    Some(msg),
+                )
+                .into();
         }
 
-        // 기본 구현 호출
-        self.token.ft_transfer_call(receiver_id, amount, memo, msg)
+        assert_one_yocto();
+        require!(env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL, "More gas is required");
+        let received =
+            self.internal_transfer_with_fees(&sender_id, &receiver_id, amount.into(), memo);
+        self.internal_notify_receiver(sender_id, receiver_id, received.into(), msg).into()
     }
 
     fn ft_total_supply(&self) -> U128 {
-        self.token.ft_total_supply()
+        (self.token.total_supply + self.reflection_pool).into()
     }
 
     fn ft_balance_of(&self, account_id: AccountId) -> U128 {
-        self.token.ft_balance_of(account_id)
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        (balance + self.internal_pending_reflection(&account_id, balance)).into()
     }
 }
 
@@ -298,10 +818,21 @@ impl FungibleTokenResolver for Contract {
         receiver_id: AccountId,
         amount: U128,
     ) -> U128 {
+        if let Some(used_amount) = self
+            .internal_resolve_frozen_transfer(&sender_id, &receiver_id, amount.0)
+            .or_else(|| self.internal_resolve_transfer_to_sink(&sender_id, &receiver_id, amount.0))
+        {
+            return used_amount.into();
+        }
+        self.before_balance_change(&sender_id);
+        self.before_balance_change(&receiver_id);
         let (used_amount, burned_amount) =
-            self.token.internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+            self.token.internal_ft_resolve_transfer(&sender_id, receiver_id.clone(), amount);
         if burned_amount > 0 {
-           self.on_tokens_burned(sender_id, burned_amount)
+            self.after_balance_change(Some(&receiver_id), None, burned_amount);
+            self.on_tokens_burned(sender_id, burned_amount)
+        } else if used_amount < amount.0 {
+            self.after_balance_change(Some(&receiver_id), Some(&sender_id), amount.0 - used_amount);
         }
         used_amount.into()
     }
@@ -314,9 +845,11 @@ mod tests {
 
     use super::*;
 
-    const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+    pub(crate) const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+    /// Covers the storage of any record a test creates.
+    pub(crate) const DEPOSIT: Balance = 10_000_000_000_000_000_000_000;
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    pub(crate) fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
             .current_account_id(accounts(0))
@@ -325,6 +858,45 @@ mod tests {
         builder
     }
 
+    /// Deploys the contract with `TOTAL_SUPPLY` owned by `owner_id`.
+    pub(crate) fn deploy(context: &mut VMContextBuilder, owner_id: AccountId) -> Contract {
+        testing_env!(context.build());
+        Contract::new_default_meta(owner_id, TOTAL_SUPPLY.into())
+    }
+
+    /// Registers each of `account_ids` with the minimum storage balance.
+    pub(crate) fn register(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        account_ids: &[AccountId],
+    ) {
+        for account_id in account_ids {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(contract.storage_balance_bounds().min.into())
+                .predecessor_account_id(account_id.clone())
+                .build());
+            contract.storage_deposit(None, None);
+        }
+    }
+
+    /// Deploys the contract owned by account 2, registers each of `account_ids`, and leaves the
+    /// owner as the caller with `deposit` attached.
+    pub(crate) fn setup_with(
+        account_ids: &[AccountId],
+        deposit: Balance,
+    ) -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(2));
+        let mut contract = deploy(&mut context, accounts(2));
+        register(&mut context, &mut contract, account_ids);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract)
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
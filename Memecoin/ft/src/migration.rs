@@ -0,0 +1,104 @@
+/*!
+State migration from the first deployed version.
+
+The first version kept the token, its metadata, the owner, the frozen accounts and the feature
+flags. After the new code is deployed, the contract account calls `migrate`, which keeps all of
+them. The state of every feature added since starts empty, except `total_minted`, which starts
+at the supply of the first version so that it keeps matching `ft_total_supply`, and
+`min_claimable_amount`, which starts at one whole token as for a new contract.
+*/
+use crate::*;
+
+/// The state of the first deployed version.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV1 {
+    token: FungibleToken,
+    metadata: LazyOption<FungibleTokenMetadata>,
+    owner_id: AccountId,
+    frozen: LookupMap<AccountId, bool>,
+    use_mint: bool,
+    use_freeze: bool,
+    use_update_metadata: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrades the state of the first version.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("No state to migrate"));
+        let total_supply = old.token.total_supply;
+        let mut this = Self::with_token(old.token, old.metadata, old.owner_id);
+        this.frozen = old.frozen;
+        this.use_mint = old.use_mint;
+        this.use_freeze = old.use_freeze;
+        this.use_update_metadata = old.use_update_metadata;
+        this.total_minted = total_supply;
+        this
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{get_context, register, TOTAL_SUPPLY};
+
+    /// Writes the state of the first version, owned by accounts(2) with accounts(1) frozen.
+    fn setup() -> VMContextBuilder {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut token = FungibleToken::new(b"a".to_vec());
+        token.internal_register_account(&accounts(2));
+        token.internal_deposit(&accounts(2), TOTAL_SUPPLY);
+        token.internal_register_account(&accounts(1));
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Example NEAR fungible token".to_string(),
+            symbol: "EXAMPLE".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 24,
+        };
+        let mut frozen = LookupMap::new(b"f".to_vec());
+        frozen.insert(&accounts(1), &true);
+        env::state_write(&ContractV1 {
+            token,
+            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
+            owner_id: accounts(2),
+            frozen,
+            use_mint: true,
+            use_freeze: true,
+            use_update_metadata: false,
+        });
+        context
+    }
+
+    #[test]
+    fn migrate_keeps_state() {
+        let mut context = setup();
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        assert!(contract.is_frozen(&accounts(1)));
+        assert_eq!(
+            contract.get_total_minted().0 - contract.get_total_burned().0,
+            contract.ft_total_supply().0
+        );
+
+        register(&mut context, &mut contract, &[accounts(3)]);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(3), 1_000.into(), None);
+        contract.mint(1_000.into());
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+        assert_eq!(contract.get_total_minted().0, TOTAL_SUPPLY + 1_000);
+    }
+}
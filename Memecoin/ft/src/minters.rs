@@ -0,0 +1,183 @@
+/*!
+Delegated minters with rolling quotas.
+
+The owner can allow other accounts to `mint` up to `quota` tokens in any `period` nanoseconds.
+Mints are counted in `MINTER_BUCKETS` buckets per period, and a bucket counts until a whole
+period has passed since it ended. So a minted amount frees up between one period and one period
+plus a bucket after it was minted, and no window of `period` nanoseconds ever holds more than
+`quota`. Minters mint to their own account, like the owner does.
+*/
+use crate::*;
+use near_sdk::json_types::U64;
+
+const MINTER_BUCKETS: u64 = 24;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Minter {
+    quota: Balance,
+    period: u64,
+    /// Amounts minted in each bucket, keyed by the end of the bucket, oldest first.
+    minted: Vec<(u64, Balance)>,
+}
+
+impl Minter {
+    /// Returns the start of the rolling window. Buckets that ended before it no longer count.
+    fn window_start(&self) -> u64 {
+        env::block_timestamp().saturating_sub(self.period)
+    }
+
+    fn remaining(&self) -> Balance {
+        let window_start = self.window_start();
+        let minted: Balance = self
+            .minted
+            .iter()
+            .filter(|(end, _)| *end > window_start)
+            .map(|(_, amount)| amount)
+            .sum();
+        self.quota.saturating_sub(minted)
+    }
+
+    /// Adds `amount` to the current bucket, and drops the buckets that no longer count.
+    fn add_minted(&mut self, amount: Balance) {
+        let window_start = self.window_start();
+        self.minted.retain(|(end, _)| *end > window_start);
+        let bucket_duration = (self.period / MINTER_BUCKETS).max(1);
+        let end = (env::block_timestamp() / bucket_duration + 1) * bucket_duration;
+        match self.minted.last_mut() {
+            Some((last_end, minted)) if *last_end == end => *minted += amount,
+            _ => self.minted.push((end, amount)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MinterQuotaSet<'a> {
+    minter_id: &'a AccountId,
+    quota: U128,
+    period: U64,
+}
+
+#[derive(Serialize)]
+struct MinterRemoved<'a> {
+    minter_id: &'a AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allows `minter_id` to mint up to `quota` tokens in any `period` nanoseconds, or changes the
+    /// quota of an existing minter without forgetting what it minted.
+    #[payable]
+    pub fn set_minter_quota(&mut self, minter_id: AccountId, quota: U128, period: U64) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(period.0 > 0, "Period must be positive");
+        let minter = match self.minters.get(&minter_id) {
+            Some(minter) => Minter { quota: quota.into(), period: period.0, ..minter },
+            None => Minter { quota: quota.into(), period: period.0, minted: vec![] },
+        };
+        self.minters.insert(&minter_id, &minter);
+        emit_nep297_event(
+            "minter_quota_set",
+            &[MinterQuotaSet { minter_id: &minter_id, quota, period }],
+        );
+    }
+
+    #[payable]
+    pub fn remove_minter(&mut self, minter_id: AccountId) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(self.minters.remove(&minter_id).is_some(), "The account is not a minter");
+        emit_nep297_event("minter_removed", &[MinterRemoved { minter_id: &minter_id }]);
+    }
+
+    pub fn is_minter(&self, account_id: AccountId) -> bool {
+        self.minters.contains_key(&account_id)
+    }
+
+    /// Returns how much `minter_id` can mint now without going over its quota.
+    pub fn minter_remaining_quota(&self, minter_id: AccountId) -> U128 {
+        self.minters.get(&minter_id).map_or(0, |minter| minter.remaining()).into()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_use_mint_quota(&mut self, minter_id: &AccountId, amount: Balance) {
+        let mut minter = self.minters.get(minter_id).unwrap_or_else(|| {
+            env::panic_str("Only the contract owner or a minter can mint tokens")
+        });
+        require!(amount <= minter.remaining(), "Mint exceeds the remaining quota");
+        minter.add_minted(amount);
+        self.minters.insert(minter_id, &minter);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::setup_with;
+
+    const HOUR: u64 = 60 * 60 * 1_000_000_000;
+    const DAY: u64 = 24 * HOUR;
+    const QUOTA: Balance = 1_000_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], ONE_YOCTO);
+        contract.update_use_mint(true);
+        contract.set_minter_quota(accounts(1), QUOTA.into(), U64(DAY));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).block_timestamp(DAY).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn quota_rolls_over_the_period() {
+        let (mut context, mut contract) = setup();
+        contract.mint((QUOTA / 4).into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, QUOTA / 4);
+
+        testing_env!(context.block_timestamp(DAY + DAY / 2).build());
+        contract.mint((QUOTA * 3 / 4).into());
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, 0);
+
+        // The first mint counts until a period after the end of its bucket.
+        testing_env!(context.block_timestamp(2 * DAY + HOUR - 1).build());
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, 0);
+        testing_env!(context.block_timestamp(2 * DAY + HOUR).build());
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, QUOTA / 4);
+        contract.mint((QUOTA / 4).into());
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, 0);
+
+        testing_env!(context.block_timestamp(2 * DAY + DAY / 2 + HOUR).build());
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, QUOTA * 3 / 4);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, QUOTA * 5 / 4);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_minter_quota(accounts(1), (QUOTA / 4).into(), U64(DAY));
+        assert_eq!(contract.minter_remaining_quota(accounts(1)).0, 0);
+    }
+
+    #[test]
+    fn quota_holds_for_any_period() {
+        let (mut context, mut contract) = setup();
+        contract.mint(QUOTA.into());
+        for timestamp in [DAY + DAY / 2, 2 * DAY - 1, 2 * DAY, 2 * DAY + HOUR - 1].iter() {
+            testing_env!(context.block_timestamp(*timestamp).build());
+            assert_eq!(contract.minter_remaining_quota(accounts(1)).0, 0);
+        }
+        testing_env!(context.block_timestamp(2 * DAY + HOUR).build());
+        contract.mint(QUOTA.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 2 * QUOTA);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint exceeds the remaining quota")]
+    fn mint_quota_fail() {
+        let (mut context, mut contract) = setup();
+        contract.mint(QUOTA.into());
+        testing_env!(context.block_timestamp(2 * DAY).build());
+        contract.mint(1.into());
+    }
+}
@@ -0,0 +1,369 @@
+/*!
+Optional external transfer policy.
+
+When `transfer_policy` is set, `ft_transfer_checked` and `ft_transfer_call` ask the policy
+contract `check_transfer(sender_id, receiver_id, amount)` before settling. The amount is held by
+the contract account while the check is in flight and is refunded to the sender if the policy
+rejects the transfer or the call fails.
+
+The other calls that move tokens from one account to another can't wait for the check, so they
+are rejected while a policy is set: `ft_transfer`, `ft_transfer_from`,
+`transfer_with_authorization`, `send_or_escrow`, `claim`, the split transfers, `pay_invoice`,
+`execute_due`, and the creation of streams, escrows, HTLCs and vesting schedules. Those created
+before the policy was set still settle as usual.
+*/
+use crate::*;
+use circulating::Lock;
+use near_contract_standards::fungible_token::events::FtBurn;
+use near_sdk::{ext_contract, PromiseError};
+
+const GAS_FOR_CHECK_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_TRANSFER_CHECKED: Gas = Gas(15_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL_CHECKED: Gas = Gas(25_000_000_000_000
+    + GAS_FOR_CHECK_TRANSFER.0
+    + GAS_FOR_ON_TRANSFER_CHECKED.0
+    + GAS_FOR_RESOLVE_TRANSFER.0);
+
+#[allow(dead_code)]
+#[ext_contract(ext_transfer_policy)]
+pub trait TransferPolicy {
+    fn check_transfer(&self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> bool;
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_transfer_policy(&mut self, policy_id: Option<AccountId>) {
+        self.only_owner();
+        assert_one_yocto();
+        emit_event("set_transfer_policy", policy_id.as_ref().map_or("", |id| id.as_str()));
+        self.transfer_policy = policy_id;
+    }
+
+    pub fn get_transfer_policy(&self) -> Option<AccountId> {
+        self.transfer_policy.clone()
+    }
+
+    /// Transfers `amount` to `receiver_id` once the transfer policy approves it.
+    /// Returns the amount that was transferred, which is 0 if the policy rejected it.
+    /// Without a policy the transfer settles immediately.
+    #[payable]
+    pub fn ft_transfer_checked(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_frozen(&sender_id, &receiver_id);
+
+        match self.transfer_policy.clone() {
+            Some(policy_id) => self
+                .internal_transfer_with_policy(
+                    policy_id,
+                    sender_id,
+                    receiver_id,
+                    amount.into(),
+                    memo,
+                    None,
+                )
+                .into(),
+            None => {
+                let received =
+                    self.internal_transfer_with_fees(&sender_id, &receiver_id, amount.into(), memo);
+                PromiseOrValue::Value(received.into())
+            }
+        }
+    }
+
+    #[private]
+    pub fn on_transfer_checked(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+        #[callback_result] approved: Result<bool, PromiseError>,
+    ) -> PromiseOrValue<U128> {
+        let approved = matches!(approved, Ok(true));
+        let sender_frozen = self.internal_is_frozen(&sender_id);
+        let receiver_frozen = self.internal_is_frozen(&receiver_id);
+        if !approved
+            || sender_frozen
+            || receiver_frozen
+            || !self.token.accounts.contains_key(&receiver_id)
+        {
+            // An approved transfer between accounts frozen in the meantime goes to the escrow.
+            let to_escrow = sender_frozen || (approved && receiver_frozen);
+            self.internal_refund_policy_escrow(sender_id, &receiver_id, amount.into(), to_escrow);
+            return PromiseOrValue::Value(U128(0));
+        }
+
+        self.internal_unlock(Lock::TransferPolicy, amount.into());
+        let received = self.internal_transfer_with_fees(
+            &env::current_account_id(),
+            &receiver_id,
+            amount.into(),
+            memo,
+        );
+        match msg {
+            None => PromiseOrValue::Value(received.into()),
+            Some(msg) => {
+                self.internal_notify_receiver(sender_id, receiver_id, received.into(), msg).into()
+            }
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_no_transfer_policy(&self) {
+        assert!(
+            self.transfer_policy.is_none(),
+            "Transfers require policy approval, use ft_transfer_checked"
+        );
+    }
+
+    pub(crate) fn internal_transfer_with_policy(
+        &mut self,
+        policy_id: AccountId,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        if msg.is_some() {
+            require!(env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL_CHECKED, "More gas is required");
+        }
+        self.internal_lock_escrow(
+            Lock::TransferPolicy,
+            &sender_id,
+            amount,
+            "transfer policy escrow",
+        );
+
+        ext_transfer_policy::ext(policy_id)
+            .with_static_gas(GAS_FOR_CHECK_TRANSFER)
+            .with_unused_gas_weight(0)
+            .check_transfer(sender_id.clone(), receiver_id.clone(), amount.into())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_TRANSFER_CHECKED)
+                    .on_transfer_checked(sender_id, receiver_id, amount.into(), memo, msg),
+            )
+    }
+
+    /// Returns escrowed tokens to the sender, or sends them to the frozen refund escrow if
+    /// `to_escrow` is set and the escrow is registered. If the sender unregistered, they go to the
+    /// sink account or are burned.
+    fn internal_refund_policy_escrow(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        to_escrow: bool,
+    ) {
+        let contract_id = env::current_account_id();
+        self.internal_unlock(Lock::TransferPolicy, amount);
+        if to_escrow
+            && self.internal_escrow_frozen_refund(&contract_id, &sender_id, receiver_id, amount)
+        {
+            return;
+        }
+        if self.token.accounts.contains_key(&sender_id) {
+            self.internal_transfer(&contract_id, &sender_id, amount, Some("refund".to_string()));
+        } else if let Some(sink_id) = self.internal_sink_account(&contract_id) {
+            self.internal_transfer(&contract_id, &sink_id, amount, Some("refund".to_string()));
+        } else {
+            self.internal_withdraw(&contract_id, amount);
+            FtBurn { owner_id: &contract_id, amount: &U128(amount), memo: Some("refund") }.emit();
+            self.on_tokens_burned(sender_id, amount);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::{Base64VecU8, U64};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{register, setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1)], ONE_YOCTO);
+        contract.set_transfer_policy(Some(accounts(3)));
+        (context, contract)
+    }
+
+    #[test]
+    fn transfer_checked_settles_on_approval() {
+        let (mut context, mut contract) = setup();
+        let transfer_amount = TOTAL_SUPPLY / 3;
+        contract.ft_transfer_checked(accounts(1), transfer_amount.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - transfer_amount);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, transfer_amount);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.on_transfer_checked(
+            accounts(2),
+            accounts(1),
+            transfer_amount.into(),
+            None,
+            None,
+            Ok(true),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
+    }
+
+    #[test]
+    fn transfer_checked_refunds_on_rejection() {
+        let (mut context, mut contract) = setup();
+        let transfer_amount = TOTAL_SUPPLY / 3;
+        contract.ft_transfer_checked(accounts(1), transfer_amount.into(), None);
+
+        contract.ft_transfer_checked(accounts(1), transfer_amount.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.on_transfer_checked(
+            accounts(2),
+            accounts(1),
+            transfer_amount.into(),
+            None,
+            None,
+            Ok(false),
+        );
+        contract.on_transfer_checked(
+            accounts(2),
+            accounts(1),
+            transfer_amount.into(),
+            None,
+            None,
+            Err(PromiseError::Failed),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    fn transfer_checked_to_frozen_receiver_goes_to_escrow() {
+        let (mut context, mut contract) = setup();
+        register(&mut context, &mut contract, &[accounts(4)]);
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.set_frozen_refund_escrow(Some(accounts(4)));
+        contract.update_use_freeze(true);
+        let transfer_amount = TOTAL_SUPPLY / 3;
+        contract.ft_transfer_checked(accounts(1), transfer_amount.into(), None);
+        contract.freeze_account(accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.on_transfer_checked(
+            accounts(2),
+            accounts(1),
+            transfer_amount.into(),
+            None,
+            None,
+            Ok(true),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, transfer_amount);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn transfer_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 3).into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn transfer_from_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer_from(accounts(2), accounts(1), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn transfer_with_authorization_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.transfer_with_authorization(
+            accounts(2),
+            accounts(1),
+            1.into(),
+            None,
+            0.into(),
+            U64(0),
+            U64(u64::MAX),
+            "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp".parse().unwrap(),
+            Base64VecU8(vec![0; 64]),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn send_or_escrow_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.send_or_escrow(accounts(1), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn claim_without_policy_check_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.claim();
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn create_subscription_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.create_subscription(accounts(1), 1.into(), U64(1), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn execute_due_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.execute_due(vec![0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn create_stream_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.create_stream(accounts(1), 1.into(), 1.into(), U64(0), U64(u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn create_escrow_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.create_escrow(accounts(1), accounts(3), 1.into(), U64(u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn lock_htlc_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.lock_htlc(accounts(1), 1.into(), Base64VecU8(vec![0; 32]), U64(u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfers require policy approval, use ft_transfer_checked")]
+    fn create_vesting_without_policy_check_fail() {
+        let (_, mut contract) = setup();
+        contract.create_vesting(accounts(1), 1.into(), U64(0), U64(0), U64(1), false);
+    }
+}
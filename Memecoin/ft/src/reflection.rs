@@ -0,0 +1,286 @@
+/*!
+Reflection mode: a fee on every transfer is redistributed to all holders.
+
+The fee is taken from the sender and added to `reflection_pool`. Instead of crediting each holder,
+`reflection_per_token` grows by `fee / eligible supply`, scaled by `REFLECTION_MAGNITUDE`. The
+reflection an account earned is `balance * (reflection_per_token - debt)`, where `debt` is the
+value of the accumulator when the account was last settled. Accounts are settled into the ledger
+right before their balance changes, so holding the tokens costs nothing.
+
+Excluded accounts, such as a DEX pool, and the contract account that holds escrowed tokens do not
+earn reflections, and their balances don't count towards the eligible supply. No fee is taken
+while the eligible supply is zero, or so small that the accumulator would overflow.
+*/
+use crate::*;
+
+const REFLECTION_MAGNITUDE: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// Returns how much `reflection_per_token` grows by when `fee` is spread over `eligible_supply`,
+/// or `None` if it doesn't fit.
+fn reflection_per_token_increase(fee: Balance, eligible_supply: Balance) -> Option<u128> {
+    if eligible_supply == 0 {
+        return None;
+    }
+    let increase = U256::from(fee) * U256::from(REFLECTION_MAGNITUDE) / U256::from(eligible_supply);
+    if increase > U256::from(u128::MAX) {
+        None
+    } else {
+        Some(increase.as_u128())
+    }
+}
+
+#[derive(Serialize)]
+struct Reflection<'a> {
+    account_id: &'a AccountId,
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn update_use_reflection(&mut self, use_reflection: bool) {
+        self.only_owner();
+        assert_one_yocto();
+        self.use_reflection = use_reflection;
+        emit_event("update_use_reflection", &use_reflection.to_string());
+    }
+
+    pub fn get_use_reflection(&self) -> bool {
+        self.use_reflection
+    }
+
+    /// Sets the share of each transfer that is redistributed, in basis points.
+    #[payable]
+    pub fn set_reflection_fee_bps(&mut self, fee_bps: u16) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(fee_bps < MAX_BPS, "Fee must be lower than 10000 basis points");
+        require!(
+            fee_bps + self.burn_on_transfer_bps < MAX_BPS,
+            "Transfer fees must be lower than 10000 basis points"
+        );
+        self.reflection_fee_bps = fee_bps;
+        emit_event("set_reflection_fee_bps", &fee_bps.to_string());
+    }
+
+    pub fn get_reflection_fee_bps(&self) -> u16 {
+        self.reflection_fee_bps
+    }
+
+    /// Stops `account_id` from earning reflections. What it earned so far is settled first.
+    #[payable]
+    pub fn exclude_from_reflection(&mut self, account_id: AccountId) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(
+            !self.is_excluded_from_reflection(account_id.clone()),
+            "Account is already excluded"
+        );
+        self.internal_settle_reflection(&account_id);
+        self.reflection_excluded.insert(&account_id);
+        self.reflection_debts.remove(&account_id);
+        self.reflection_excluded_supply += self.token.accounts.get(&account_id).unwrap_or(0);
+        emit_event("exclude_from_reflection", account_id.as_str());
+    }
+
+    /// Lets `account_id` earn reflections again, starting from the next transfer.
+    #[payable]
+    pub fn include_in_reflection(&mut self, account_id: AccountId) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(self.reflection_excluded.remove(&account_id), "Account is not excluded");
+        self.reflection_debts.insert(&account_id, &self.reflection_per_token);
+        self.reflection_excluded_supply -= self.token.accounts.get(&account_id).unwrap_or(0);
+        emit_event("include_in_reflection", account_id.as_str());
+    }
+
+    pub fn is_excluded_from_reflection(&self, account_id: AccountId) -> bool {
+        account_id == env::current_account_id() || self.reflection_excluded.contains(&account_id)
+    }
+
+    /// Returns the total amount of fees redistributed so far that holders haven't moved yet.
+    pub fn get_reflection_pool(&self) -> U128 {
+        self.reflection_pool.into()
+    }
+}
+
+impl Contract {
+    /// Returns the reflections `account_id` earned on its `balance` since it was last settled.
+    pub(crate) fn internal_pending_reflection(
+        &self,
+        account_id: &AccountId,
+        balance: Balance,
+    ) -> Balance {
+        if balance == 0 || self.is_excluded_from_reflection(account_id.clone()) {
+            return 0;
+        }
+        let debt = self.reflection_debts.get(account_id).unwrap_or(0);
+        mul_div(balance, self.reflection_per_token - debt, REFLECTION_MAGNITUDE)
+    }
+
+    /// Moves the pending reflections of `account_id` from the pool into its ledger balance.
+    pub(crate) fn internal_settle_reflection(&mut self, account_id: &AccountId) {
+        if self.is_excluded_from_reflection(account_id.clone()) {
+            return;
+        }
+        let debt = self.reflection_debts.get(account_id).unwrap_or(0);
+        if debt == self.reflection_per_token {
+            return;
+        }
+        let balance = match self.token.accounts.get(account_id) {
+            Some(balance) => balance,
+            None => return,
+        };
+        self.reflection_debts.insert(account_id, &self.reflection_per_token);
+        let pending = mul_div(balance, self.reflection_per_token - debt, REFLECTION_MAGNITUDE);
+        if pending > 0 {
+            self.internal_snapshot_balance(account_id, balance);
+            self.internal_settle_dividends(account_id, balance);
+            self.token.accounts.insert(account_id, &(balance + pending));
+            self.token.total_supply += pending;
+            self.reflection_pool -= pending;
+            self.internal_move_delegate_votes(None, Some(account_id), pending);
+        }
+    }
+
+    /// Returns the share of a transfer of `amount` that is redistributed.
+    pub(crate) fn internal_reflection_fee(&self, amount: Balance) -> Balance {
+        if !self.use_reflection {
+            return 0;
+        }
+        mul_div(amount, self.reflection_fee_bps.into(), MAX_BPS.into())
+    }
+
+    /// Takes `fee` from `account_id` and spreads it over the eligible supply, unless the
+    /// accumulator can't take it.
+    pub(crate) fn internal_reflect(&mut self, account_id: &AccountId, fee: Balance) {
+        // The supply that is eligible once the fee left the balance of `account_id`.
+        let mut excluded_supply = self.reflection_excluded_supply;
+        if self.is_excluded_from_reflection(account_id.clone()) {
+            excluded_supply -= fee;
+        }
+        let eligible_supply = self.token.total_supply - fee - excluded_supply;
+        let reflection_per_token = match reflection_per_token_increase(fee, eligible_supply)
+            .and_then(|increase| self.reflection_per_token.checked_add(increase))
+        {
+            Some(reflection_per_token) => reflection_per_token,
+            None => {
+                log!("The eligible supply can't take a reflection of {}", fee);
+                return;
+            }
+        };
+        self.internal_withdraw(account_id, fee);
+        self.reflection_pool += fee;
+        self.reflection_per_token = reflection_per_token;
+        emit_nep297_event("reflection", &[Reflection { account_id, amount: fee.into() }]);
+    }
+
+    /// Keeps the supply held by excluded accounts in sync with their balances.
+    pub(crate) fn internal_track_reflection_excluded(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        let from_excluded = from.map(|id| self.is_excluded_from_reflection(id.clone()));
+        let to_excluded = to.map(|id| self.is_excluded_from_reflection(id.clone()));
+        if from_excluded == Some(true) {
+            self.reflection_excluded_supply -= amount;
+        }
+        if to_excluded == Some(true) {
+            self.reflection_excluded_supply += amount;
+        }
+    }
+
+    /// Forgets the debt of an account that was removed, after its reflections were settled.
+    pub(crate) fn internal_remove_reflection_debt(&mut self, account_id: &AccountId) {
+        self.reflection_debts.remove(account_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::ONE_YOCTO;
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1), accounts(3)], ONE_YOCTO);
+        contract.update_use_reflection(true);
+        contract.set_reflection_fee_bps(1_000);
+        (context, contract)
+    }
+
+    #[test]
+    fn reflect_to_holders() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 2).into(), None);
+
+        // A tenth of the transfer is spread over the remaining 19/20 of the supply.
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY * 9 / 19);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY * 10 / 19);
+
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 19).into(), None);
+        let total = contract.ft_balance_of(accounts(1)).0 + contract.ft_balance_of(accounts(2)).0;
+        assert!((TOTAL_SUPPLY - 2..=TOTAL_SUPPLY).contains(&total));
+    }
+
+    #[test]
+    fn excluded_accounts_do_not_earn() {
+        let (_, mut contract) = setup();
+        contract.exclude_from_reflection(accounts(3));
+        contract.ft_transfer(accounts(3), (TOTAL_SUPPLY / 2).into(), None);
+        let pool_balance = TOTAL_SUPPLY * 9 / 20;
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, pool_balance);
+        // The owner is the only eligible holder and gets the whole fee back.
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - pool_balance);
+
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 10).into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, pool_balance);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+
+        contract.include_in_reflection(accounts(3));
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 10).into(), None);
+        assert!(contract.ft_balance_of(accounts(3)).0 > pool_balance);
+    }
+
+    #[test]
+    fn no_fee_without_eligible_supply() {
+        let (_, mut contract) = setup();
+        contract.exclude_from_reflection(accounts(2));
+        contract.exclude_from_reflection(accounts(3));
+        let fee = TOTAL_SUPPLY / 20;
+        contract.ft_transfer(accounts(3), (TOTAL_SUPPLY / 2).into(), None);
+        // Nobody could get the fee, so the sender keeps it.
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, TOTAL_SUPPLY / 2 - fee);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY / 2 + fee);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_reflection_pool().0, 0);
+    }
+
+    #[test]
+    fn reflection_per_token_increase_overflow() {
+        assert_eq!(reflection_per_token_increase(1, 0), None);
+        assert_eq!(reflection_per_token_increase(1_000, 1), Some(1_000 * REFLECTION_MAGNITUDE));
+        assert_eq!(reflection_per_token_increase(TOTAL_SUPPLY, 1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fee must be lower than 10000 basis points")]
+    fn set_reflection_fee_bps_fail() {
+        let (_, mut contract) = setup();
+        contract.set_reflection_fee_bps(10_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Transfer fees must be lower than 10000 basis points")]
+    fn set_transfer_fees_fail() {
+        let (_, mut contract) = setup();
+        contract.set_burn_on_transfer_bps(1_000);
+        contract.set_reflection_fee_bps(8_999);
+        contract.set_reflection_fee_bps(9_000);
+    }
+}
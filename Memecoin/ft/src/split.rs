@@ -0,0 +1,226 @@
+/*!
+Split transfers.
+
+`ft_split_transfer` sends one amount to several recipients, each getting its share in basis
+points of what is left after the transfer fees. The shares must add up to 10000. Each share is
+rounded down and the remainder goes to the first recipient. All recipients are paid in the same
+call, which emits a single `ft_transfer` event with an entry per recipient.
+`ft_split_transfer_call` also calls `ft_on_transfer` on every recipient, resolves each of them
+like `ft_transfer_call`, and returns the total amount the recipients used.
+*/
+use crate::*;
+use near_contract_standards::fungible_token::events::FtTransfer;
+use near_sdk::PromiseResult;
+
+const MAX_SPLIT_RECIPIENTS: usize = 10;
+const GAS_FOR_ON_SPLIT_TRANSFER_RESOLVED: Gas = Gas(5_000_000_000_000);
+
+/// Splits `amount` by the basis points of the recipients, rounding down and giving the remainder
+/// to the first recipient.
+fn split_amount(amount: Balance, recipients: &[(AccountId, u16)]) -> Vec<Balance> {
+    let mut shares: Vec<Balance> =
+        recipients.iter().map(|(_, bps)| mul_div(amount, (*bps).into(), MAX_BPS.into())).collect();
+    shares[0] += amount - shares.iter().sum::<Balance>();
+    shares
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn ft_split_transfer(
+        &mut self,
+        recipients: Vec<(AccountId, u16)>,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_no_transfer_policy();
+        let sender_id = env::predecessor_account_id();
+        self.internal_split_transfer(&sender_id, &recipients, amount.into(), memo);
+    }
+
+    #[payable]
+    pub fn ft_split_transfer_call(
+        &mut self,
+        recipients: Vec<(AccountId, u16)>,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        self.assert_no_transfer_policy();
+        require!(
+            env::prepaid_gas()
+                > GAS_FOR_FT_TRANSFER_CALL * recipients.len() as u64
+                    + GAS_FOR_ON_SPLIT_TRANSFER_RESOLVED,
+            "More gas is required"
+        );
+        let sender_id = env::predecessor_account_id();
+        let shares = self.internal_split_transfer(&sender_id, &recipients, amount.into(), memo);
+        let notifications = recipients
+            .into_iter()
+            .zip(shares)
+            .filter(|(_, share)| *share > 0)
+            .map(|((receiver_id, _), share)| {
+                self.internal_notify_receiver(
+                    sender_id.clone(),
+                    receiver_id,
+                    share.into(),
+                    msg.clone(),
+                )
+            })
+            .reduce(|promise, next| promise.and(next));
+        // The fees can take the whole amount, which leaves no recipient to notify.
+        match notifications {
+            Some(promise) => promise
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(GAS_FOR_ON_SPLIT_TRANSFER_RESOLVED)
+                        .on_split_transfer_resolved(),
+                )
+                .into(),
+            None => PromiseOrValue::Value(U128(0)),
+        }
+    }
+
+    /// Adds up the amounts used by the recipients of `ft_split_transfer_call`.
+    #[private]
+    pub fn on_split_transfer_resolved(&self) -> U128 {
+        let used_amount: Balance = (0..env::promise_results_count())
+            .map(|index| match env::promise_result(index) {
+                PromiseResult::Successful(value) => {
+                    serde_json::from_slice::<U128>(&value).map_or(0, |amount| amount.0)
+                }
+                _ => 0,
+            })
+            .sum();
+        used_amount.into()
+    }
+}
+
+impl Contract {
+    /// Moves the shares of `amount` from `sender_id` to the recipients, charges the transfer
+    /// fees once for the whole amount, and returns the shares.
+    fn internal_split_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        recipients: &[(AccountId, u16)],
+        amount: Balance,
+        memo: Option<String>,
+    ) -> Vec<Balance> {
+        require!(
+            !recipients.is_empty() && recipients.len() <= MAX_SPLIT_RECIPIENTS,
+            "Must have between 1 and 10 recipients"
+        );
+        require!(
+            recipients.iter().map(|(_, bps)| u32::from(*bps)).sum::<u32>() == u32::from(MAX_BPS),
+            "Shares must add up to 10000 basis points"
+        );
+        require!(amount > 0, "The amount should be a positive number");
+        for (index, (receiver_id, _)) in recipients.iter().enumerate() {
+            self.assert_not_frozen(sender_id, receiver_id);
+            require!(receiver_id != sender_id, "Sender and receiver should be different");
+            require!(
+                !recipients[..index].iter().any(|(account_id, _)| account_id == receiver_id),
+                "Duplicate recipient"
+            );
+        }
+
+        let reflection_fee = self.internal_reflection_fee(amount);
+        let burn_amount = self.internal_transfer_burn_amount(amount);
+        let shares = split_amount(amount - reflection_fee - burn_amount, recipients);
+        for ((receiver_id, _), share) in recipients.iter().zip(&shares) {
+            if *share == 0 {
+                continue;
+            }
+            self.internal_transfer_without_event(sender_id, receiver_id, *share);
+        }
+
+        let amounts: Vec<U128> = shares.iter().map(|share| U128(*share)).collect();
+        let events: Vec<FtTransfer> = recipients
+            .iter()
+            .zip(&amounts)
+            .filter(|(_, amount)| amount.0 > 0)
+            .map(|((receiver_id, _), amount)| FtTransfer {
+                old_owner_id: sender_id,
+                new_owner_id: receiver_id,
+                amount,
+                memo: memo.as_deref(),
+            })
+            .collect();
+        FtTransfer::emit_many(&events);
+        self.internal_charge_transfer_fees(sender_id, burn_amount, reflection_fee);
+        shares
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        setup_with(&[accounts(1), accounts(3), accounts(4)], ONE_YOCTO)
+    }
+
+    #[test]
+    fn split_transfer_rounds_to_first_recipient() {
+        let (_, mut contract) = setup();
+        contract.ft_split_transfer(
+            vec![(accounts(1), 7_000), (accounts(3), 2_000), (accounts(4), 1_000)],
+            1_001.into(),
+            None,
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 701);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 1_001);
+
+        let transfers: Vec<_> = get_logs()
+            .into_iter()
+            .filter(|log| log.contains("\"event\":\"ft_transfer\""))
+            .collect();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].matches("new_owner_id").count(), 3);
+    }
+
+    #[test]
+    fn split_transfer_call_adds_up_used_amounts() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.prepaid_gas(Gas(300_000_000_000_000)).build());
+        contract.ft_split_transfer_call(
+            vec![(accounts(1), 5_000), (accounts(3), 5_000)],
+            1_000.into(),
+            None,
+            String::new(),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 500);
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![
+                PromiseResult::Successful(b"\"500\"".to_vec()),
+                PromiseResult::Successful(b"\"200\"".to_vec()),
+            ],
+        );
+        assert_eq!(contract.on_split_transfer_resolved().0, 700);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shares must add up to 10000 basis points")]
+    fn split_transfer_fail() {
+        let (_, mut contract) = setup();
+        contract.ft_split_transfer(
+            vec![(accounts(1), 7_000), (accounts(3), 2_000)],
+            1_000.into(),
+            None,
+        );
+    }
+}
@@ -0,0 +1,247 @@
+/*!
+Storage sponsorship pool.
+
+The owner funds the pool with NEAR and allows sponsor accounts to spend up to `limit` of it.
+`sponsor_storage_deposit` registers accounts with storage paid from the pool, including the
+record of who sponsored them. When a sponsored account calls `storage_unregister`, its storage
+deposit goes back to the pool instead of the account, and the sponsor can spend it again.
+*/
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Sponsor {
+    limit: Balance,
+    used: Balance,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SponsoredAccount {
+    sponsor_id: AccountId,
+    deposit: Balance,
+}
+
+#[derive(Serialize)]
+pub struct SponsorView {
+    limit: U128,
+    used: U128,
+}
+
+#[derive(Serialize)]
+struct SponsorSet<'a> {
+    sponsor_id: &'a AccountId,
+    limit: U128,
+}
+
+#[derive(Serialize)]
+struct SponsorRemoved<'a> {
+    sponsor_id: &'a AccountId,
+}
+
+#[derive(Serialize)]
+struct SponsorshipPoolChanged {
+    amount: U128,
+    pool: U128,
+}
+
+#[derive(Serialize)]
+struct StorageSponsored<'a> {
+    sponsor_id: &'a AccountId,
+    account_id: AccountId,
+    deposit: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the sponsorship pool.
+    #[payable]
+    pub fn fund_sponsorship_pool(&mut self) -> U128 {
+        self.only_owner();
+        let amount = env::attached_deposit();
+        require!(amount > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        self.sponsorship_pool += amount;
+        emit_nep297_event(
+            "sponsorship_pool_funded",
+            &[SponsorshipPoolChanged { amount: amount.into(), pool: self.sponsorship_pool.into() }],
+        );
+        self.sponsorship_pool.into()
+    }
+
+    /// Sends `amount` of the pool back to the owner.
+    #[payable]
+    pub fn withdraw_sponsorship_pool(&mut self, amount: U128) -> U128 {
+        self.only_owner();
+        assert_one_yocto();
+        require!(amount.0 <= self.sponsorship_pool, "Not enough NEAR in the sponsorship pool");
+        self.sponsorship_pool -= amount.0;
+        Promise::new(self.owner_id.clone()).transfer(amount.0);
+        emit_nep297_event(
+            "sponsorship_pool_withdrawn",
+            &[SponsorshipPoolChanged { amount, pool: self.sponsorship_pool.into() }],
+        );
+        self.sponsorship_pool.into()
+    }
+
+    /// Allows `sponsor_id` to have up to `limit` of the pool spent on accounts it registered, or
+    /// changes the limit of an existing sponsor.
+    #[payable]
+    pub fn set_sponsor(&mut self, sponsor_id: AccountId, limit: U128) {
+        self.only_owner();
+        assert_one_yocto();
+        let used = self.sponsors.get(&sponsor_id).map_or(0, |sponsor| sponsor.used);
+        self.sponsors.insert(&sponsor_id, &Sponsor { limit: limit.into(), used });
+        emit_nep297_event("sponsor_set", &[SponsorSet { sponsor_id: &sponsor_id, limit }]);
+    }
+
+    /// Stops `sponsor_id` from registering accounts. Accounts it already registered still
+    /// return their storage deposit to the pool.
+    #[payable]
+    pub fn remove_sponsor(&mut self, sponsor_id: AccountId) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(self.sponsors.remove(&sponsor_id).is_some(), "The account is not a sponsor");
+        emit_nep297_event("sponsor_removed", &[SponsorRemoved { sponsor_id: &sponsor_id }]);
+    }
+
+    /// Registers the accounts that aren't registered yet with storage paid from the pool, and
+    /// returns them.
+    #[payable]
+    pub fn sponsor_storage_deposit(&mut self, account_ids: Vec<AccountId>) -> Vec<AccountId> {
+        assert_one_yocto();
+        let sponsor_id = env::predecessor_account_id();
+        let mut sponsor = self
+            .sponsors
+            .get(&sponsor_id)
+            .unwrap_or_else(|| env::panic_str("The account is not a sponsor"));
+
+        let mut events = vec![];
+        for account_id in account_ids {
+            if self.token.accounts.contains_key(&account_id) {
+                continue;
+            }
+            let initial_storage_usage = env::storage_usage();
+            self.token.internal_register_account(&account_id);
+            let mut sponsored = SponsoredAccount { sponsor_id: sponsor_id.clone(), deposit: 0 };
+            self.sponsored_accounts.insert(&account_id, &sponsored);
+            // The record has a fixed size, so updating the deposit doesn't change the cost.
+            let deposit = Balance::from(env::storage_usage() - initial_storage_usage)
+                * env::storage_byte_cost();
+            require!(deposit <= self.sponsorship_pool, "The sponsorship pool is exhausted");
+            require!(sponsor.used + deposit <= sponsor.limit, "Exceeds the sponsor limit");
+            self.sponsorship_pool -= deposit;
+            sponsor.used += deposit;
+            sponsored.deposit = deposit;
+            self.sponsored_accounts.insert(&account_id, &sponsored);
+            events.push(StorageSponsored {
+                sponsor_id: &sponsor_id,
+                account_id,
+                deposit: deposit.into(),
+            });
+        }
+        self.sponsors.insert(&sponsor_id, &sponsor);
+        if !events.is_empty() {
+            emit_nep297_event("storage_sponsored", &events);
+        }
+        events.into_iter().map(|event| event.account_id).collect()
+    }
+
+    pub fn get_sponsorship_pool(&self) -> U128 {
+        self.sponsorship_pool.into()
+    }
+
+    pub fn get_sponsor(&self, sponsor_id: AccountId) -> Option<SponsorView> {
+        self.sponsors
+            .get(&sponsor_id)
+            .map(|sponsor| SponsorView { limit: sponsor.limit.into(), used: sponsor.used.into() })
+    }
+
+    /// Returns the sponsor that paid for the storage of `account_id`, if any.
+    pub fn get_storage_sponsor(&self, account_id: AccountId) -> Option<AccountId> {
+        self.sponsored_accounts.get(&account_id).map(|sponsored| sponsored.sponsor_id)
+    }
+}
+
+impl Contract {
+    /// Unregisters the predecessor like `FungibleToken::internal_storage_unregister`, but returns
+    /// its storage deposit to the pool and frees it up in the sponsor's limit.
+    pub(crate) fn internal_unregister_sponsored(
+        &mut self,
+        sponsored: SponsoredAccount,
+        force: Option<bool>,
+    ) -> Option<(AccountId, Balance)> {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.token.internal_unwrap_balance_of(&account_id);
+        require!(
+            balance == 0 || force.unwrap_or(false),
+            "Can't unregister the account with the positive balance without force"
+        );
+        self.token.accounts.remove(&account_id);
+        self.token.total_supply -= balance;
+
+        self.sponsorship_pool += sponsored.deposit;
+        if let Some(mut sponsor) = self.sponsors.get(&sponsored.sponsor_id) {
+            sponsor.used = sponsor.used.saturating_sub(sponsored.deposit);
+            self.sponsors.insert(&sponsored.sponsor_id, &sponsor);
+        }
+        emit_nep297_event(
+            "sponsored_storage_refunded",
+            &[StorageSponsored {
+                sponsor_id: &sponsored.sponsor_id,
+                account_id: account_id.clone(),
+                deposit: sponsored.deposit.into(),
+            }],
+        );
+        Some((account_id, balance))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::setup_with;
+
+    fn setup(limit: Balance) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[], ONE_NEAR);
+        contract.fund_sponsorship_pool();
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        contract.set_sponsor(accounts(3), limit.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn sponsored_storage_returns_to_pool() {
+        let (mut context, mut contract) = setup(ONE_NEAR);
+        let registered =
+            contract.sponsor_storage_deposit(vec![accounts(0), accounts(1), accounts(2)]);
+        assert_eq!(registered, vec![accounts(0), accounts(1)]);
+        assert!(contract.storage_balance_of(accounts(1)).is_some());
+        assert_eq!(contract.get_storage_sponsor(accounts(1)), Some(accounts(3)));
+        assert_eq!(contract.get_storage_sponsor(accounts(2)), None);
+        let used = contract.get_sponsor(accounts(3)).unwrap().used.0;
+        assert!(used > 0);
+        assert_eq!(contract.get_sponsorship_pool().0, ONE_NEAR - used);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert!(contract.storage_unregister(None));
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        assert_eq!(contract.get_storage_sponsor(accounts(1)), None);
+        let remaining = contract.get_sponsor(accounts(3)).unwrap().used.0;
+        assert!(remaining > 0 && remaining < used);
+        assert_eq!(contract.get_sponsorship_pool().0, ONE_NEAR - remaining);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeds the sponsor limit")]
+    fn sponsor_limit_fail() {
+        let (_, mut contract) = setup(1);
+        contract.sponsor_storage_deposit(vec![accounts(0)]);
+    }
+}
@@ -0,0 +1,378 @@
+/*!
+Per-second token streams.
+
+`create_stream` escrows `deposit` tokens of the sender, which stream to the receiver at
+`rate_per_sec` from `start` until `stop`, or until the whole deposit has streamed. The receiver
+can `withdraw_from_stream` what has streamed so far at any time. Either party can
+`cancel_stream`, which pays the receiver what has streamed and returns the rest to the sender. An
+account can be a party to up to 100 open streams.
+Canceling fails if an account that gets a share of the deposit is frozen.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::U64;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+const DEFAULT_STREAMS_LIMIT: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Stream {
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    rate_per_sec: Balance,
+    deposit: Balance,
+    start: u64,
+    stop: u64,
+    withdrawn: Balance,
+    storage_cost: Balance,
+}
+
+impl Stream {
+    fn streamed(&self) -> Balance {
+        let now = env::block_timestamp().min(self.stop);
+        if now <= self.start {
+            return 0;
+        }
+        let elapsed = u128::from(now - self.start);
+        mul_div(self.rate_per_sec, elapsed, NANOS_PER_SECOND).min(self.deposit)
+    }
+}
+
+#[derive(Serialize)]
+pub struct StreamView {
+    stream_id: u64,
+    sender_id: AccountId,
+    receiver_id: AccountId,
+    rate_per_sec: U128,
+    deposit: U128,
+    start: U64,
+    stop: U64,
+    withdrawn: U128,
+}
+
+#[derive(Serialize)]
+struct StreamCreated<'a> {
+    stream_id: u64,
+    sender_id: &'a AccountId,
+    receiver_id: &'a AccountId,
+    rate_per_sec: U128,
+    deposit: U128,
+    start: U64,
+    stop: U64,
+}
+
+#[derive(Serialize)]
+struct StreamWithdrawn<'a> {
+    stream_id: u64,
+    receiver_id: &'a AccountId,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+struct StreamCanceled<'a> {
+    stream_id: u64,
+    canceled_by: &'a AccountId,
+    receiver_amount: U128,
+    sender_amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `deposit` tokens of the caller to stream to `receiver_id`, and returns the id of
+    /// the stream.
+    #[payable]
+    pub fn create_stream(
+        &mut self,
+        receiver_id: AccountId,
+        rate_per_sec: U128,
+        deposit: U128,
+        start: U64,
+        stop: U64,
+    ) -> u64 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let sender_id = env::predecessor_account_id();
+        self.assert_not_frozen(&sender_id, &receiver_id);
+        self.assert_no_transfer_policy();
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        require!(deposit.0 > 0, "The amount should be a positive number");
+        require!(start.0 < stop.0, "The stream must start before it stops");
+        require!(stop.0 > env::block_timestamp(), "The stream must stop in the future");
+        require!(
+            mul_div(rate_per_sec.0, (stop.0 - start.0).into(), NANOS_PER_SECOND) >= deposit.0,
+            "The deposit must stream out by the stop time"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.internal_lock_escrow(Lock::Stream, &sender_id, deposit.0, "stream");
+
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 1;
+        let mut stream = Stream {
+            sender_id: sender_id.clone(),
+            receiver_id: receiver_id.clone(),
+            rate_per_sec: rate_per_sec.0,
+            deposit: deposit.0,
+            start: start.0,
+            stop: stop.0,
+            withdrawn: 0,
+            storage_cost: 0,
+        };
+        self.streams.insert(&stream_id, &stream);
+        push_account_ids(&mut self.account_streams, &[&sender_id, &receiver_id], stream_id);
+        stream.storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        self.streams.insert(&stream_id, &stream);
+
+        emit_nep297_event(
+            "stream_created",
+            &[StreamCreated {
+                stream_id,
+                sender_id: &sender_id,
+                receiver_id: &receiver_id,
+                rate_per_sec,
+                deposit,
+                start,
+                stop,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        stream_id
+    }
+
+    /// Pays the receiver everything that has streamed and was not withdrawn yet.
+    #[payable]
+    pub fn withdraw_from_stream(&mut self, stream_id: u64) -> U128 {
+        assert_one_yocto();
+        let mut stream = self.internal_unwrap_stream(stream_id);
+        require!(
+            env::predecessor_account_id() == stream.receiver_id,
+            "Only the receiver can withdraw from the stream"
+        );
+        self.assert_receiver_not_frozen(&stream.receiver_id);
+        let amount = stream.streamed() - stream.withdrawn;
+        require!(amount > 0, "Nothing to withdraw");
+        stream.withdrawn += amount;
+        let receiver_id = stream.receiver_id.clone();
+        if stream.withdrawn == stream.deposit {
+            self.internal_close_stream(stream_id, stream);
+        } else {
+            self.streams.insert(&stream_id, &stream);
+        }
+
+        self.internal_release_escrow(
+            Lock::Stream,
+            &receiver_id,
+            amount,
+            Some("stream".to_string()),
+        );
+        emit_nep297_event(
+            "stream_withdrawn",
+            &[StreamWithdrawn { stream_id, receiver_id: &receiver_id, amount: amount.into() }],
+        );
+        amount.into()
+    }
+
+    /// Closes the stream, paying the receiver what has streamed and returning the rest to the
+    /// sender. Either of them can cancel.
+    #[payable]
+    pub fn cancel_stream(&mut self, stream_id: u64) {
+        assert_one_yocto();
+        let stream = self.internal_unwrap_stream(stream_id);
+        let canceled_by = env::predecessor_account_id();
+        require!(
+            canceled_by == stream.sender_id || canceled_by == stream.receiver_id,
+            "Only the sender or the receiver can cancel the stream"
+        );
+        let streamed = stream.streamed();
+        let receiver_amount = streamed - stream.withdrawn;
+        let sender_amount = stream.deposit - streamed;
+        if receiver_amount > 0 {
+            self.assert_receiver_not_frozen(&stream.receiver_id);
+        }
+        if sender_amount > 0 {
+            self.assert_receiver_not_frozen(&stream.sender_id);
+        }
+        let sender_id = stream.sender_id.clone();
+        let receiver_id = stream.receiver_id.clone();
+        self.internal_close_stream(stream_id, stream);
+
+        if receiver_amount > 0 {
+            self.internal_release_escrow(
+                Lock::Stream,
+                &receiver_id,
+                receiver_amount,
+                Some("stream".to_string()),
+            );
+        }
+        if sender_amount > 0 {
+            self.internal_release_escrow(
+                Lock::Stream,
+                &sender_id,
+                sender_amount,
+                Some("stream canceled".to_string()),
+            );
+        }
+        emit_nep297_event(
+            "stream_canceled",
+            &[StreamCanceled {
+                stream_id,
+                canceled_by: &canceled_by,
+                receiver_amount: receiver_amount.into(),
+                sender_amount: sender_amount.into(),
+            }],
+        );
+    }
+
+    pub fn get_stream(&self, stream_id: u64) -> Option<StreamView> {
+        self.streams.get(&stream_id).map(|stream| stream_view(stream_id, stream))
+    }
+
+    /// Returns what the receiver can withdraw from the stream right now.
+    pub fn withdrawable_from_stream(&self, stream_id: u64) -> U128 {
+        self.streams.get(&stream_id).map_or(0, |stream| stream.streamed() - stream.withdrawn).into()
+    }
+
+    /// Returns the open streams that `account_id` sends or receives, oldest first.
+    pub fn get_streams(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<StreamView> {
+        let limit = limit.unwrap_or(DEFAULT_STREAMS_LIMIT);
+        account_ids_page(&self.account_streams, &account_id, from_index, limit)
+            .into_iter()
+            .map(|stream_id| stream_view(stream_id, self.streams.get(&stream_id).unwrap()))
+            .collect()
+    }
+}
+
+fn stream_view(stream_id: u64, stream: Stream) -> StreamView {
+    StreamView {
+        stream_id,
+        sender_id: stream.sender_id,
+        receiver_id: stream.receiver_id,
+        rate_per_sec: stream.rate_per_sec.into(),
+        deposit: stream.deposit.into(),
+        start: stream.start.into(),
+        stop: stream.stop.into(),
+        withdrawn: stream.withdrawn.into(),
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_stream(&self, stream_id: u64) -> Stream {
+        self.streams.get(&stream_id).unwrap_or_else(|| env::panic_str("Stream not found"))
+    }
+
+    /// Removes the stream and refunds its storage to the sender.
+    fn internal_close_stream(&mut self, stream_id: u64, stream: Stream) {
+        self.streams.remove(&stream_id);
+        remove_account_ids(
+            &mut self.account_streams,
+            &[&stream.sender_id, &stream.receiver_id],
+            stream_id,
+        );
+        if stream.storage_cost > 0 {
+            Promise::new(stream.sender_id).transfer(stream.storage_cost);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    const SECOND: u64 = 1_000_000_000;
+    const RATE: Balance = 10;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        contract.create_stream(
+            accounts(1),
+            RATE.into(),
+            (100 * RATE).into(),
+            U64(100 * SECOND),
+            U64(200 * SECOND),
+        );
+        contract.create_stream(
+            accounts(1),
+            RATE.into(),
+            (100 * RATE).into(),
+            U64(100 * SECOND),
+            U64(200 * SECOND),
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn withdraw_from_stream() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 200 * RATE);
+        testing_env!(context
+            .block_timestamp(130 * SECOND)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.withdrawable_from_stream(0).0, 30 * RATE);
+        assert_eq!(contract.withdraw_from_stream(0).0, 30 * RATE);
+        assert_eq!(contract.withdrawable_from_stream(0).0, 0);
+
+        testing_env!(context.block_timestamp(300 * SECOND).build());
+        assert_eq!(contract.withdraw_from_stream(0).0, 70 * RATE);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100 * RATE);
+        assert!(contract.get_stream(0).is_none());
+        let streams = contract.get_streams(accounts(2), None, None);
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].stream_id, 1);
+    }
+
+    #[test]
+    fn cancel_stream_splits_balance() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .block_timestamp(125 * SECOND)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.withdraw_from_stream(1);
+        testing_env!(context
+            .block_timestamp(140 * SECOND)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.cancel_stream(1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 40 * RATE);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100 * RATE - 40 * RATE);
+
+        let streams = contract.get_streams(accounts(1), Some(0), Some(10));
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].stream_id, 0);
+        assert!(contract.get_streams(accounts(1), Some(1), None).is_empty());
+    }
+
+    #[test]
+    fn cancel_ended_stream_of_frozen_sender() {
+        let (mut context, mut contract) = setup();
+        contract.update_use_freeze(true);
+        contract.freeze_account(accounts(2));
+        testing_env!(context
+            .block_timestamp(300 * SECOND)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.cancel_stream(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100 * RATE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the receiver can withdraw from the stream")]
+    fn withdraw_from_stream_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(130 * SECOND).build());
+        contract.withdraw_from_stream(0);
+    }
+}
@@ -0,0 +1,172 @@
+/*!
+Lifetime minted and burned totals.
+
+Every mint and burn adds to `total_minted` or `total_burned`, starting with the initial supply,
+so `total_minted - total_burned` always equals `ft_total_supply`. This includes the tokens
+burned by `ft_resolve_transfer` when the sender closed its account, and the balances destroyed
+by a forced `storage_unregister`, with the reflections they had not settled yet. The last
+`SUPPLY_HISTORY_CAPACITY` changes are also kept in a ring buffer, with the time of the change and
+the total supply right after it.
+*/
+use crate::*;
+use near_sdk::json_types::U64;
+
+const SUPPLY_HISTORY_CAPACITY: u64 = 100;
+const DEFAULT_SUPPLY_HISTORY_LIMIT: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum SupplyChangeKind {
+    Mint,
+    Burn,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SupplyChange {
+    kind: SupplyChangeKind,
+    account_id: AccountId,
+    amount: Balance,
+    total_supply: Balance,
+    timestamp: u64,
+}
+
+#[derive(Serialize)]
+pub struct SupplyChangeView {
+    /// Position of the change since the contract was deployed.
+    index: u64,
+    kind: SupplyChangeKind,
+    account_id: AccountId,
+    amount: U128,
+    total_supply: U128,
+    timestamp: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_total_minted(&self) -> U128 {
+        self.total_minted.into()
+    }
+
+    pub fn get_total_burned(&self) -> U128 {
+        self.total_burned.into()
+    }
+
+    /// Returns the supply changes that are still in the history, oldest first.
+    pub fn get_supply_history(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<SupplyChangeView> {
+        let oldest = self.supply_changes_count.saturating_sub(SUPPLY_HISTORY_CAPACITY);
+        (oldest..self.supply_changes_count)
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_SUPPLY_HISTORY_LIMIT) as usize)
+            .map(|index| {
+                let change = self.supply_history.get(&(index % SUPPLY_HISTORY_CAPACITY)).unwrap();
+                SupplyChangeView {
+                    index,
+                    kind: change.kind,
+                    account_id: change.account_id,
+                    amount: change.amount.into(),
+                    total_supply: change.total_supply.into(),
+                    timestamp: change.timestamp.into(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Records `amount` minted to or burned from `account_id`, after the balance changed.
+    pub(crate) fn internal_record_supply_change(
+        &mut self,
+        kind: SupplyChangeKind,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        match kind {
+            SupplyChangeKind::Mint => self.total_minted += amount,
+            SupplyChangeKind::Burn => self.total_burned += amount,
+        }
+        let change = SupplyChange {
+            kind,
+            account_id: account_id.clone(),
+            amount,
+            total_supply: self.ft_total_supply().0,
+            timestamp: env::block_timestamp(),
+        };
+        self.supply_history.insert(&(self.supply_changes_count % SUPPLY_HISTORY_CAPACITY), &change);
+        self.supply_changes_count += 1;
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        setup_with(&[accounts(1)], ONE_YOCTO)
+    }
+
+    #[test]
+    fn tracks_minted_and_burned() {
+        let (mut context, mut contract) = setup();
+        contract.update_use_mint(true);
+        contract.mint(1_000.into());
+        contract.ft_transfer(accounts(1), 500.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.storage_unregister(Some(true));
+        assert_eq!(contract.get_total_minted().0, TOTAL_SUPPLY + 1_000);
+        assert_eq!(contract.get_total_burned().0, 500);
+        assert_eq!(
+            contract.get_total_minted().0 - contract.get_total_burned().0,
+            contract.ft_total_supply().0
+        );
+
+        let history = contract.get_supply_history(None, None);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].kind, SupplyChangeKind::Mint);
+        assert_eq!(history[2].kind, SupplyChangeKind::Burn);
+        assert_eq!(history[2].account_id, accounts(1));
+        assert_eq!(history[2].total_supply.0, TOTAL_SUPPLY + 500);
+    }
+
+    #[test]
+    fn closed_account_burns_its_reflections() {
+        let (mut context, mut contract) = setup();
+        contract.update_use_reflection(true);
+        contract.set_reflection_fee_bps(1_000);
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 2).into(), None);
+        contract.ft_transfer(accounts(1), (TOTAL_SUPPLY / 10).into(), None);
+        let balance = contract.ft_balance_of(accounts(1)).0;
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.storage_unregister(Some(true));
+        assert_eq!(contract.get_total_burned().0, balance);
+        assert_eq!(
+            contract.get_total_minted().0 - contract.get_total_burned().0,
+            contract.ft_total_supply().0
+        );
+    }
+
+    #[test]
+    fn history_keeps_the_latest_changes() {
+        let (mut context, mut contract) = setup();
+        contract.update_use_mint(true);
+        for _ in 0..SUPPLY_HISTORY_CAPACITY + 5 {
+            testing_env!(context.storage_usage(env::storage_usage()).build());
+            contract.mint(1.into());
+        }
+        let history = contract.get_supply_history(None, Some(SUPPLY_HISTORY_CAPACITY + 10));
+        assert_eq!(history.len() as u64, SUPPLY_HISTORY_CAPACITY);
+        assert_eq!(history[0].index, 6);
+        assert_eq!(history.last().unwrap().index, SUPPLY_HISTORY_CAPACITY + 5);
+    }
+}
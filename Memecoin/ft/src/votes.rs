@@ -0,0 +1,393 @@
+/*!
+Voting power that follows delegation.
+
+An account's balance only counts as votes once it delegates them, to itself or to someone else.
+Each delegatee keeps a list of `(timestamp, votes)` checkpoints that is updated on every balance
+change of its delegators, so the voting power at any past block timestamp can be looked up. A
+delegator pays for its delegation and for the checkpoints its balance changes add out of its
+available storage balance, and a balance change it can't pay the checkpoint of fails. A delegation
+must leave enough of it for `DELEGATION_CHECKPOINTS` checkpoints of the delegatee. `delegate` adds
+what is attached above one yocto to the available storage balance of the caller.
+
+`delegate_by_sig` lets a relayer submit a delegation signed with a key registered through
+`register_authorization_key`. The signature must cover the borsh serialization of
+`DelegationAuthorization`:
+
+```text
+domain: String          "vrtx-ft/delegate/v1"
+contract_id: AccountId  this contract
+delegator: AccountId
+delegatee: AccountId
+nonce: u64              separate from the nonces of transfer authorizations
+expiry: u64             block timestamp in nanoseconds
+```
+*/
+use crate::*;
+use authorization::AuthorizationKind;
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::{require, ONE_YOCTO};
+
+pub(crate) const DELEGATION_AUTHORIZATION_DOMAIN: &str = "vrtx-ft/delegate/v1";
+/// Checkpoints a delegator must keep the storage of available after delegating.
+const DELEGATION_CHECKPOINTS: u64 = 10;
+/// Storage of a vote checkpoint without the delegatee id: the record, the map prefix, the id
+/// length and the index of the key, and the value.
+const VOTE_CHECKPOINT_STORAGE_USAGE: StorageUsage = 40 + 1 + 4 + 4 + 8 + 16;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VoteCheckpoint {
+    timestamp: u64,
+    votes: Balance,
+}
+
+#[derive(BorshSerialize)]
+pub struct DelegationAuthorization {
+    pub domain: String,
+    pub contract_id: AccountId,
+    pub delegator: AccountId,
+    pub delegatee: AccountId,
+    pub nonce: u64,
+    pub expiry: u64,
+}
+
+#[derive(Serialize)]
+struct DelegateChanged<'a> {
+    delegator_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_delegate_id: Option<&'a AccountId>,
+    to_delegate_id: &'a AccountId,
+}
+
+#[derive(Serialize)]
+struct DelegateVotesChanged<'a> {
+    delegate_id: &'a AccountId,
+    previous_votes: U128,
+    new_votes: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Delegates the caller's voting power to `delegatee`.
+    #[payable]
+    pub fn delegate(&mut self, delegatee: AccountId) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let delegator = env::predecessor_account_id();
+        let deposit = env::attached_deposit() - ONE_YOCTO;
+        if deposit > 0 {
+            if !self.token.accounts.contains_key(&delegator) {
+                env::panic_str(&format!("The account {} is not registered", delegator));
+            }
+            self.internal_deposit_available_storage(&delegator, deposit);
+        }
+        self.internal_delegate(delegator, delegatee);
+    }
+
+    /// Same as `delegate`, with a signature of the delegator so that the caller pays the gas. The
+    /// attached deposit pays for the used nonce.
+    #[payable]
+    pub fn delegate_by_sig(
+        &mut self,
+        delegator: AccountId,
+        delegatee: AccountId,
+        nonce: U64,
+        expiry: U64,
+        public_key: PublicKey,
+        signature: Base64VecU8,
+    ) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(env::block_timestamp() < expiry.0, "The authorization has expired");
+        let message = DelegationAuthorization {
+            domain: DELEGATION_AUTHORIZATION_DOMAIN.to_string(),
+            contract_id: env::current_account_id(),
+            delegator: delegator.clone(),
+            delegatee: delegatee.clone(),
+            nonce: nonce.0,
+            expiry: expiry.0,
+        };
+        let initial_storage_usage = env::storage_usage();
+        self.internal_use_authorization(
+            &delegator,
+            AuthorizationKind::Delegation,
+            nonce.0,
+            &public_key,
+            &message.try_to_vec().unwrap(),
+            &signature.0,
+        );
+        self.internal_delegate(delegator, delegatee);
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    pub fn delegates(&self, account_id: AccountId) -> Option<AccountId> {
+        self.delegates.get(&account_id)
+    }
+
+    pub fn get_votes(&self, account_id: AccountId) -> U128 {
+        self.internal_last_vote_checkpoint(&account_id).map_or(0, |c| c.votes).into()
+    }
+
+    /// Returns the votes of `account_id` at the end of the block with the given `timestamp`,
+    /// which must be in the past.
+    pub fn get_past_votes(&self, account_id: AccountId, timestamp: U64) -> U128 {
+        require!(timestamp.0 < env::block_timestamp(), "Timestamp must be in the past");
+        // Finds the number of checkpoints taken at or before `timestamp`.
+        let (mut low, mut high) = (0, self.vote_checkpoint_counts.get(&account_id).unwrap_or(0));
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.internal_vote_checkpoint(&account_id, middle).timestamp <= timestamp.0 {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        match low {
+            0 => U128(0),
+            _ => self.internal_vote_checkpoint(&account_id, low - 1).votes.into(),
+        }
+    }
+}
+
+impl Contract {
+    fn internal_delegate(&mut self, delegator: AccountId, delegatee: AccountId) {
+        self.internal_settle_reflection(&delegator);
+        let balance = self.token.accounts.get(&delegator).unwrap_or(0);
+        let initial_storage_usage = env::storage_usage();
+        let previous = self.delegates.insert(&delegator, &delegatee);
+        self.internal_charge_storage(&delegator, initial_storage_usage);
+        emit_nep297_event(
+            "delegate_changed",
+            &[DelegateChanged {
+                delegator_id: &delegator,
+                from_delegate_id: previous.as_ref(),
+                to_delegate_id: &delegatee,
+            }],
+        );
+        self.internal_move_votes(
+            previous.as_ref(),
+            Some(&delegatee),
+            balance,
+            Some(&delegator),
+            Some(&delegator),
+        );
+        let reserve = Balance::from(
+            (VOTE_CHECKPOINT_STORAGE_USAGE + delegatee.as_str().len() as StorageUsage)
+                * DELEGATION_CHECKPOINTS,
+        ) * env::storage_byte_cost();
+        if self.internal_available_storage(&delegator) < reserve {
+            env::panic_str(&format!(
+                "The delegation needs {} yoctoNEAR of available storage balance for future checkpoints",
+                reserve
+            ));
+        }
+    }
+
+    /// Moves the votes that come with `amount` tokens changing hands from the delegate of
+    /// `from` to the delegate of `to`. `None` stands for minted or burned tokens.
+    pub(crate) fn internal_move_delegate_votes(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        let from_delegate = from.and_then(|account_id| self.delegates.get(account_id));
+        let to_delegate = to.and_then(|account_id| self.delegates.get(account_id));
+        self.internal_move_votes(from_delegate.as_ref(), to_delegate.as_ref(), amount, from, to);
+    }
+
+    /// Moves `amount` votes between delegates. The checkpoints each side adds are charged to
+    /// its payer, or to the caller's deposit without one.
+    fn internal_move_votes(
+        &mut self,
+        from_delegate: Option<&AccountId>,
+        to_delegate: Option<&AccountId>,
+        amount: Balance,
+        from_payer: Option<&AccountId>,
+        to_payer: Option<&AccountId>,
+    ) {
+        if amount == 0 || from_delegate == to_delegate {
+            return;
+        }
+        if let Some(delegate_id) = from_delegate {
+            self.internal_write_votes(delegate_id, from_payer, |votes| {
+                votes
+                    .checked_sub(amount)
+                    .unwrap_or_else(|| env::panic_str("The delegate has fewer votes than moved"))
+            });
+        }
+        if let Some(delegate_id) = to_delegate {
+            self.internal_write_votes(delegate_id, to_payer, |votes| votes + amount);
+        }
+    }
+
+    fn internal_vote_checkpoint(&self, delegate_id: &AccountId, index: u32) -> VoteCheckpoint {
+        self.vote_checkpoints.get(&(delegate_id.clone(), index)).unwrap()
+    }
+
+    fn internal_last_vote_checkpoint(&self, delegate_id: &AccountId) -> Option<VoteCheckpoint> {
+        match self.vote_checkpoint_counts.get(delegate_id) {
+            Some(count) if count > 0 => Some(self.internal_vote_checkpoint(delegate_id, count - 1)),
+            _ => None,
+        }
+    }
+
+    fn internal_write_votes(
+        &mut self,
+        delegate_id: &AccountId,
+        payer_id: Option<&AccountId>,
+        op: impl FnOnce(Balance) -> Balance,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        let timestamp = env::block_timestamp();
+        let last = self.internal_last_vote_checkpoint(delegate_id);
+        let previous_votes = last.as_ref().map_or(0, |c| c.votes);
+        let new_votes = op(previous_votes);
+        let count = self.vote_checkpoint_counts.get(delegate_id).unwrap_or(0);
+        // Several changes in the same block share a checkpoint.
+        let index = match last {
+            Some(last) if last.timestamp == timestamp => count - 1,
+            _ => {
+                self.vote_checkpoint_counts.insert(delegate_id, &(count + 1));
+                count
+            }
+        };
+        self.vote_checkpoints
+            .insert(&(delegate_id.clone(), index), &VoteCheckpoint { timestamp, votes: new_votes });
+        if let Some(payer_id) = payer_id {
+            self.internal_charge_storage(payer_id, initial_storage_usage);
+        }
+        emit_nep297_event(
+            "delegate_votes_changed",
+            &[DelegateVotesChanged {
+                delegate_id,
+                previous_votes: previous_votes.into(),
+                new_votes: new_votes.into(),
+            }],
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use ed25519_dalek::Signer;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::authorization::tests::{keypair, near_public_key};
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], 2 * DEPOSIT);
+        testing_env!(context.block_timestamp(10).build());
+        contract.storage_deposit(None, None);
+        (context, contract)
+    }
+
+    #[test]
+    fn votes_follow_delegation() {
+        let (mut context, mut contract) = setup();
+        contract.delegate(accounts(3));
+        assert_eq!(contract.get_votes(accounts(3)).0, TOTAL_SUPPLY);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .block_timestamp(20)
+            .build());
+        let transfer_amount = TOTAL_SUPPLY / 4;
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        assert_eq!(contract.get_votes(accounts(3)).0, TOTAL_SUPPLY - transfer_amount);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * DEPOSIT)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(30)
+            .build());
+        contract.delegate(accounts(1));
+        assert_eq!(contract.get_votes(accounts(1)).0, transfer_amount);
+
+        testing_env!(context.block_timestamp(40).build());
+        assert_eq!(contract.get_past_votes(accounts(3), U64(5)).0, 0);
+        assert_eq!(contract.get_past_votes(accounts(3), U64(15)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.get_past_votes(accounts(3), U64(20)).0, TOTAL_SUPPLY - transfer_amount);
+        assert_eq!(contract.get_past_votes(accounts(1), U64(25)).0, 0);
+        assert_eq!(contract.get_past_votes(accounts(1), U64(30)).0, transfer_amount);
+    }
+
+    #[test]
+    fn delegate_deposit_is_available() {
+        let (_, mut contract) = setup();
+        let available = contract.internal_available_storage(&accounts(2));
+        let initial_storage_usage = env::storage_usage();
+        contract.delegate(accounts(3));
+        let cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        assert_eq!(
+            contract.internal_available_storage(&accounts(2)),
+            available + 2 * DEPOSIT - ONE_YOCTO - cost
+        );
+    }
+
+    #[test]
+    fn delegate_by_sig() {
+        let (mut context, mut contract) = setup();
+        contract.register_authorization_key(near_public_key(&keypair()));
+
+        let message = DelegationAuthorization {
+            domain: DELEGATION_AUTHORIZATION_DOMAIN.to_string(),
+            contract_id: accounts(0),
+            delegator: accounts(2),
+            delegatee: accounts(3),
+            nonce: 1,
+            expiry: 1_000,
+        };
+        let signature = keypair().sign(&message.try_to_vec().unwrap()).to_bytes().to_vec();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.delegate_by_sig(
+            accounts(2),
+            accounts(3),
+            U64(1),
+            U64(1_000),
+            near_public_key(&keypair()),
+            signature.into(),
+        );
+        assert_eq!(contract.delegates(accounts(2)), Some(accounts(3)));
+        assert_eq!(contract.get_votes(accounts(3)).0, TOTAL_SUPPLY);
+        // Transfer authorizations have nonces of their own.
+        assert!(!contract.is_authorization_used(accounts(2), U64(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "more of available storage balance")]
+    fn delegate_without_storage_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.delegate(accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "for future checkpoints")]
+    fn delegate_checkpoint_storage_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT / 2)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.delegate(accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Timestamp must be in the past")]
+    fn get_past_votes_fail() {
+        let (_, contract) = setup();
+        contract.get_past_votes(accounts(3), U64(10));
+    }
+}
@@ -0,0 +1,85 @@
+//! Helpers shared by the sandbox tests, which run the token and the mock contracts next to it
+//! as compiled contracts.
+#![allow(dead_code)]
+
+use near_workspaces::network::Sandbox;
+use near_workspaces::result::ExecutionFinalResult;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, AccountId, Contract, Worker};
+use serde_json::json;
+
+pub const TOTAL_SUPPLY: u128 = 1_000_000_000_000_000;
+
+/// Builds the contract in `project_path`, relative to this crate, and deploys it.
+pub async fn deploy(worker: &Worker<Sandbox>, project_path: &str) -> anyhow::Result<Contract> {
+    let wasm = near_workspaces::compile_project(project_path).await?;
+    Ok(worker.dev_deploy(&wasm).await?)
+}
+
+/// Deploys the token with `TOTAL_SUPPLY` owned by `owner` and freezing enabled.
+pub async fn deploy_token(worker: &Worker<Sandbox>, owner: &Account) -> anyhow::Result<Contract> {
+    let token = deploy(worker, "../ft").await?;
+    token
+        .call("new")
+        .args_json(json!({
+            "owner_id": owner.id(),
+            "total_supply": TOTAL_SUPPLY.to_string(),
+            "metadata": {
+                "spec": "ft-1.0.0",
+                "name": "Example NEAR fungible token",
+                "symbol": "EXAMPLE",
+                "decimals": 24,
+            },
+            "use_mint": false,
+            "use_freeze": true,
+            "use_update_metadata": false,
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(token)
+}
+
+/// Registers `account_id` with the token, paid for by `payer`.
+pub async fn register(
+    token: &Contract,
+    payer: &Account,
+    account_id: &AccountId,
+) -> anyhow::Result<()> {
+    payer
+        .call(token.id(), "storage_deposit")
+        .args_json(json!({ "account_id": account_id }))
+        .deposit(NearToken::from_millinear(10))
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(())
+}
+
+/// Calls `method_name` of the token as `account` with one yoctoNEAR and all the gas.
+pub async fn call_token(
+    token: &Contract,
+    account: &Account,
+    method_name: &str,
+    args: serde_json::Value,
+) -> anyhow::Result<ExecutionFinalResult> {
+    Ok(account
+        .call(token.id(), method_name)
+        .args_json(args)
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?)
+}
+
+pub async fn balance_of(token: &Contract, account_id: &AccountId) -> anyhow::Result<u128> {
+    let balance: String =
+        token.view("ft_balance_of").args_json(json!({ "account_id": account_id })).await?.json()?;
+    Ok(balance.parse()?)
+}
+
+/// Returns the U128 an `ExecutionFinalResult` returned.
+pub fn returned_amount(result: ExecutionFinalResult) -> anyhow::Result<u128> {
+    let amount: String = result.into_result()?.json()?;
+    Ok(amount.parse()?)
+}
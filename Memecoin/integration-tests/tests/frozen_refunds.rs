@@ -0,0 +1,95 @@
+//! Refunds of `ft_transfer_call` to accounts frozen while the transfer is in flight, against the
+//! mock receiver in `test-contract-receiver`, which owns the token, returns part of the tokens it
+//! gets and freezes an account before returning them.
+mod common;
+
+use common::*;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+const AMOUNT: u128 = 1_000;
+const REFUND: u128 = 400;
+
+struct Setup {
+    token: Contract,
+    receiver: Contract,
+    alice: Account,
+}
+
+/// Deploys the token owned by the receiver, and sends `AMOUNT` to `alice`.
+async fn setup() -> anyhow::Result<Setup> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let receiver = deploy(&worker, "../test-contract-receiver").await?;
+    let token = deploy_token(&worker, receiver.as_account()).await?;
+    receiver
+        .call("new")
+        .args_json(json!({ "owner_id": owner.id(), "token_id": token.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    register(&token, &alice, alice.id()).await?;
+    owner
+        .call(receiver.id(), "send")
+        .args_json(json!({ "receiver_id": alice.id(), "amount": AMOUNT.to_string() }))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    Ok(Setup { token, receiver, alice })
+}
+
+/// Transfers `AMOUNT` from `alice` to the receiver, which returns `REFUND` of it after freezing
+/// `freeze`, and returns the used amount and the logs of the transfer.
+async fn transfer_call(
+    setup: &Setup,
+    freeze: Option<&Account>,
+) -> anyhow::Result<(u128, Vec<String>)> {
+    let msg = json!({ "refund": REFUND.to_string(), "freeze": freeze.map(|account| account.id()) });
+    let result = call_token(
+        &setup.token,
+        &setup.alice,
+        "ft_transfer_call",
+        json!({
+            "receiver_id": setup.receiver.id(),
+            "amount": AMOUNT.to_string(),
+            "msg": msg.to_string(),
+        }),
+    )
+    .await?;
+    let logs = result.logs().into_iter().map(String::from).collect();
+    Ok((returned_amount(result)?, logs))
+}
+
+#[tokio::test]
+async fn partial_refund() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    assert_eq!(transfer_call(&setup, None).await?.0, AMOUNT - REFUND);
+    assert_eq!(balance_of(&setup.token, setup.alice.id()).await?, REFUND);
+    assert_eq!(balance_of(&setup.token, setup.receiver.id()).await?, TOTAL_SUPPLY - REFUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn refund_from_frozen_receiver_goes_to_sender() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let (used, logs) = transfer_call(&setup, Some(setup.receiver.as_account())).await?;
+    assert_eq!(used, AMOUNT - REFUND);
+    assert_eq!(balance_of(&setup.token, setup.alice.id()).await?, REFUND);
+    assert_eq!(balance_of(&setup.token, setup.receiver.id()).await?, TOTAL_SUPPLY - REFUND);
+    assert!(logs.iter().any(|log| log.contains("\"event\":\"frozen_receiver_refunded\"")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn refund_to_frozen_sender_goes_to_escrow() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    // The escrow is the owner of the token, which is the receiver itself.
+    let (used, logs) = transfer_call(&setup, Some(&setup.alice)).await?;
+    assert_eq!(used, AMOUNT);
+    assert_eq!(balance_of(&setup.token, setup.alice.id()).await?, 0);
+    assert_eq!(balance_of(&setup.token, setup.receiver.id()).await?, TOTAL_SUPPLY);
+    assert!(logs.iter().any(|log| log.contains("\"event\":\"frozen_refund_escrowed\"")));
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! `ft_transfer_checked` and `ft_transfer_call` against the mock policy contract in
+//! `test-contract-policy`, which rejects transfers above a maximum amount and transfers from or
+//! to blocked accounts.
+mod common;
+
+use common::*;
+use near_workspaces::network::Sandbox;
+use near_workspaces::{Account, Contract, Worker};
+use serde_json::json;
+
+const MAX_AMOUNT: u128 = TOTAL_SUPPLY / 2;
+
+struct Setup {
+    worker: Worker<Sandbox>,
+    token: Contract,
+    policy: Contract,
+    owner: Account,
+    alice: Account,
+}
+
+/// Deploys the token owned by `owner` with the policy set, and registers `alice`.
+async fn setup() -> anyhow::Result<Setup> {
+    let worker = near_workspaces::sandbox().await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let token = deploy_token(&worker, &owner).await?;
+    register(&token, &alice, alice.id()).await?;
+
+    let policy = deploy(&worker, "../test-contract-policy").await?;
+    policy
+        .call("new")
+        .args_json(json!({ "owner_id": owner.id(), "max_amount": MAX_AMOUNT.to_string() }))
+        .transact()
+        .await?
+        .into_result()?;
+    call_token(&token, &owner, "set_transfer_policy", json!({ "policy_id": policy.id() }))
+        .await?
+        .into_result()?;
+    Ok(Setup { worker, token, policy, owner, alice })
+}
+
+/// Transfers `amount` from the owner to `alice` with `ft_transfer_checked`, and returns the
+/// amount that was transferred.
+async fn transfer_checked(setup: &Setup, amount: u128) -> anyhow::Result<u128> {
+    let result = call_token(
+        &setup.token,
+        &setup.owner,
+        "ft_transfer_checked",
+        json!({ "receiver_id": setup.alice.id(), "amount": amount.to_string() }),
+    )
+    .await?;
+    returned_amount(result)
+}
+
+/// Checks that nothing moved, and that the contract account doesn't hold escrowed tokens.
+async fn assert_refunded(setup: &Setup) -> anyhow::Result<()> {
+    assert_eq!(balance_of(&setup.token, setup.owner.id()).await?, TOTAL_SUPPLY);
+    assert_eq!(balance_of(&setup.token, setup.alice.id()).await?, 0);
+    assert_eq!(balance_of(&setup.token, setup.token.id()).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfer_checked_settles_on_approval() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    assert_eq!(transfer_checked(&setup, MAX_AMOUNT).await?, MAX_AMOUNT);
+    assert_eq!(balance_of(&setup.token, setup.owner.id()).await?, TOTAL_SUPPLY - MAX_AMOUNT);
+    assert_eq!(balance_of(&setup.token, setup.alice.id()).await?, MAX_AMOUNT);
+    assert_eq!(balance_of(&setup.token, setup.token.id()).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfer_checked_refunds_on_rejection() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    assert_eq!(transfer_checked(&setup, MAX_AMOUNT + 1).await?, 0);
+    assert_refunded(&setup).await?;
+
+    setup
+        .owner
+        .call(setup.policy.id(), "set_blocked")
+        .args_json(json!({ "account_id": setup.alice.id(), "blocked": true }))
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(transfer_checked(&setup, 1).await?, 0);
+    assert_refunded(&setup).await
+}
+
+#[tokio::test]
+async fn transfer_checked_refunds_on_failed_check() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    // An account without a contract can't answer the check.
+    let no_policy = setup.worker.dev_create_account().await?;
+    call_token(
+        &setup.token,
+        &setup.owner,
+        "set_transfer_policy",
+        json!({ "policy_id": no_policy.id() }),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(transfer_checked(&setup, 1).await?, 0);
+    assert_refunded(&setup).await
+}
+
+#[tokio::test]
+async fn transfer_checked_refunds_unregistered_receiver() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let bob = setup.worker.dev_create_account().await?;
+    let result = call_token(
+        &setup.token,
+        &setup.owner,
+        "ft_transfer_checked",
+        json!({ "receiver_id": bob.id(), "amount": "1" }),
+    )
+    .await?;
+    assert_eq!(returned_amount(result)?, 0);
+    assert_refunded(&setup).await
+}
+
+#[tokio::test]
+async fn transfer_call_notifies_receiver_on_approval() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let defi = deploy(&setup.worker, "../test-contract-defi").await?;
+    defi.call("new")
+        .args_json(json!({ "fungible_token_account_id": setup.token.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    register(&setup.token, &setup.owner, defi.id()).await?;
+
+    // The receiver returns 400 of the tokens it gets.
+    let result = call_token(
+        &setup.token,
+        &setup.owner,
+        "ft_transfer_call",
+        json!({ "receiver_id": defi.id(), "amount": "1000", "msg": "400" }),
+    )
+    .await?;
+    assert_eq!(returned_amount(result)?, 600);
+    assert_eq!(balance_of(&setup.token, setup.owner.id()).await?, TOTAL_SUPPLY - 600);
+    assert_eq!(balance_of(&setup.token, defi.id()).await?, 600);
+    assert_eq!(balance_of(&setup.token, setup.token.id()).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transfer_without_policy_check_fails() -> anyhow::Result<()> {
+    let setup = setup().await?;
+    let result = call_token(
+        &setup.token,
+        &setup.owner,
+        "ft_transfer",
+        json!({ "receiver_id": setup.alice.id(), "amount": "1" }),
+    )
+    .await?;
+    assert!(format!("{:?}", result.into_result().unwrap_err())
+        .contains("Transfers require policy approval, use ft_transfer_checked"));
+    assert_refunded(&setup).await
+}
@@ -0,0 +1,48 @@
+/*!
+Some hypothetical compliance contract that approves or rejects token transfers
+*/
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupSet;
+use near_sdk::json_types::U128;
+use near_sdk::{env, log, near_bindgen, AccountId, Balance, PanicOnDefault};
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Policy {
+    owner_id: AccountId,
+    max_amount: Balance,
+    blocked: LookupSet<AccountId>,
+}
+
+#[near_bindgen]
+impl Policy {
+    #[init]
+    pub fn new(owner_id: AccountId, max_amount: U128) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        Self { owner_id, max_amount: max_amount.into(), blocked: LookupSet::new(b"b".to_vec()) }
+    }
+
+    pub fn set_blocked(&mut self, account_id: AccountId, blocked: bool) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the owner can block accounts");
+        if blocked {
+            self.blocked.insert(&account_id);
+        } else {
+            self.blocked.remove(&account_id);
+        }
+    }
+
+    /// Rejects transfers from or to a blocked account and transfers above `max_amount`.
+    pub fn check_transfer(&self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> bool {
+        let approved = !self.blocked.contains(&sender_id)
+            && !self.blocked.contains(&receiver_id)
+            && amount.0 <= self.max_amount;
+        log!(
+            "in check_transfer from @{} to @{} of {}, approved = {}",
+            sender_id,
+            receiver_id,
+            amount.0,
+            approved
+        );
+        approved
+    }
+}
@@ -0,0 +1,85 @@
+/*!
+Some hypothetical contract that owns the token, keeps part of the tokens transferred to it and can
+freeze an account while a transfer to it is in flight
+*/
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, serde_json, AccountId, PanicOnDefault, Promise,
+    PromiseOrValue,
+};
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Receiver {
+    owner_id: AccountId,
+    token_id: AccountId,
+}
+
+/// The `msg` of a transfer to this contract: how many of the tokens to return, and the account to
+/// freeze before returning them.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct TransferMsg {
+    refund: U128,
+    freeze: Option<AccountId>,
+}
+
+#[ext_contract(ext_token)]
+pub trait Token {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn freeze_account(&mut self, account_id: AccountId);
+}
+
+#[near_bindgen]
+impl Receiver {
+    #[init]
+    pub fn new(owner_id: AccountId, token_id: AccountId) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        Self { owner_id, token_id }
+    }
+
+    /// Sends `amount` of the tokens this contract owns to `receiver_id`.
+    pub fn send(&mut self, receiver_id: AccountId, amount: U128) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the owner can send tokens");
+        ext_token::ext(self.token_id.clone()).with_attached_deposit(1).ft_transfer(
+            receiver_id,
+            amount,
+            None,
+        )
+    }
+
+    #[private]
+    pub fn return_refund(&self, refund: U128) -> U128 {
+        refund
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Receiver {
+    /// Returns the `refund` of `msg`, once the token froze the `freeze` account if there is one.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.token_id,
+            "Only supports the one fungible token contract"
+        );
+        log!("in {} tokens from @{} ft_on_transfer, msg = {}", amount.0, sender_id, msg);
+        let msg: TransferMsg = serde_json::from_str(&msg).expect("Invalid message");
+        match msg.freeze {
+            None => PromiseOrValue::Value(msg.refund),
+            Some(account_id) => ext_token::ext(self.token_id.clone())
+                .with_attached_deposit(1)
+                .freeze_account(account_id)
+                .then(Self::ext(env::current_account_id()).return_refund(msg.refund))
+                .into(),
+        }
+    }
+}
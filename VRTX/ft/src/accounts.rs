@@ -0,0 +1,102 @@
+/*!
+Batch views of account state.
+
+`ft_balances_of` and `get_account_states` read many accounts in a single call, for indexers and
+wallets that would otherwise make one `ft_balance_of` and `storage_balance_of` call per account.
+*/
+use crate::*;
+
+const MAX_ACCOUNTS_PER_VIEW: usize = 100;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountState {
+    balance: U128,
+    /// `None` if the account is not registered.
+    storage_balance: Option<StorageBalance>,
+    /// Tokens of the vesting schedules the account benefits from that were not released yet,
+    /// whether they have vested or not. `get_vestings` splits them by schedule. The tokens it has
+    /// in streams, escrows and HTLCs are not included.
+    vesting_balance: U128,
+    /// Tokens sent to the account with `send_or_escrow` that it can claim.
+    claimable_balance: U128,
+    storage_sponsor: Option<AccountId>,
+    delegate: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the balance of each account, in the same order.
+    pub fn ft_balances_of(&self, account_ids: Vec<AccountId>) -> Vec<U128> {
+        assert_max_accounts(&account_ids);
+        account_ids.into_iter().map(|account_id| self.ft_balance_of(account_id)).collect()
+    }
+
+    /// Returns the state of each account, in the same order.
+    pub fn get_account_states(&self, account_ids: Vec<AccountId>) -> Vec<AccountState> {
+        assert_max_accounts(&account_ids);
+        account_ids
+            .into_iter()
+            .map(|account_id| AccountState {
+                balance: self.ft_balance_of(account_id.clone()),
+                storage_balance: self.storage_balance_of(account_id.clone()),
+                vesting_balance: self.internal_vesting_balance(&account_id).into(),
+                claimable_balance: self.internal_claimable_amount(&account_id).into(),
+                storage_sponsor: self.get_storage_sponsor(account_id.clone()),
+                delegate: self.delegates.get(&account_id),
+            })
+            .collect()
+    }
+}
+
+fn assert_max_accounts(account_ids: &[AccountId]) {
+    require!(account_ids.len() <= MAX_ACCOUNTS_PER_VIEW, "At most 100 accounts per call");
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::json_types::U64;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1)], ONE_YOCTO);
+        contract.ft_transfer(accounts(1), 1_000.into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn batch_views() {
+        let (_, contract) = setup();
+        let account_ids = vec![accounts(1), accounts(2), accounts(3)];
+        assert_eq!(
+            contract.ft_balances_of(account_ids.clone()),
+            vec![U128(1_000), U128(TOTAL_SUPPLY - 1_000), U128(0)]
+        );
+        let states = contract.get_account_states(account_ids);
+        assert_eq!(states[0].balance.0, 1_000);
+        assert!(states[0].storage_balance.is_some());
+        assert!(states[2].storage_balance.is_none());
+    }
+
+    #[test]
+    fn vesting_state() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(DEPOSIT).build());
+        contract.create_vesting(accounts(1), 400.into(), U64(0), U64(0), U64(100), false);
+        testing_env!(context.block_timestamp(25).build());
+        let state = &contract.get_account_states(vec![accounts(1)])[0];
+        assert_eq!(state.balance.0, 1_000);
+        assert_eq!(state.vesting_balance.0, 400);
+
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.release(0);
+        assert_eq!(contract.get_account_states(vec![accounts(1)])[0].vesting_balance.0, 300);
+    }
+}
@@ -0,0 +1,226 @@
+/*!
+Allowances for delegated transfers.
+
+An owner approves a spender for up to `amount` tokens, optionally until `expires_at` (block
+timestamp in nanoseconds). The spender can then move the tokens with `ft_transfer_from` without
+a signature from the owner per transfer. An allowance that is used up or approved at 0 is removed,
+and the storage of its record goes back to the owner.
+*/
+use crate::*;
+use near_sdk::json_types::U64;
+use near_sdk::require;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Allowance {
+    amount: Balance,
+    expires_at: Option<u64>,
+}
+
+impl Allowance {
+    fn available(&self) -> Balance {
+        match self.expires_at {
+            Some(expires_at) if expires_at <= env::block_timestamp() => 0,
+            _ => self.amount,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtApprove<'a> {
+    owner_id: &'a AccountId,
+    spender_id: &'a AccountId,
+    amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtRevoke<'a> {
+    owner_id: &'a AccountId,
+    spender_id: &'a AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allows `spender_id` to transfer up to `amount` of the caller's tokens, replacing any
+    /// previous allowance.
+    #[payable]
+    pub fn ft_approve(&mut self, spender_id: AccountId, amount: U128, expires_at: Option<U64>) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let owner_id = env::predecessor_account_id();
+        require!(owner_id != spender_id, "Owner and spender should be different");
+        self.token.internal_unwrap_balance_of(&owner_id);
+        if let Some(expires_at) = expires_at {
+            require!(expires_at.0 > env::block_timestamp(), "Expiration must be in the future");
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        let key = (owner_id.clone(), spender_id.clone());
+        if amount.0 > 0 {
+            self.allowances.insert(
+                &key,
+                &Allowance { amount: amount.into(), expires_at: expires_at.map(|t| t.0) },
+            );
+        } else {
+            self.allowances.remove(&key);
+        }
+        emit_nep297_event(
+            "ft_approve",
+            &[FtApprove {
+                owner_id: &owner_id,
+                spender_id: &spender_id,
+                amount: &amount,
+                expires_at,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    /// Removes the allowance of `spender_id` and refunds the storage of the approval record.
+    #[payable]
+    pub fn ft_revoke(&mut self, spender_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        if self.allowances.remove(&(owner_id.clone(), spender_id.clone())).is_none() {
+            env::panic_str("No allowance to revoke");
+        }
+        emit_nep297_event(
+            "ft_revoke",
+            &[FtRevoke { owner_id: &owner_id, spender_id: &spender_id }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    pub fn ft_allowance(&self, owner_id: AccountId, spender_id: AccountId) -> U128 {
+        self.allowances.get(&(owner_id, spender_id)).map_or(0, |a| a.available()).into()
+    }
+
+    /// Transfers `amount` from `owner_id` to `receiver_id` on behalf of the owner, consuming the
+    /// caller's allowance.
+    #[payable]
+    pub fn ft_transfer_from(
+        &mut self,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        let spender_id = env::predecessor_account_id();
+
+        let key = (owner_id.clone(), spender_id);
+        let mut allowance =
+            self.allowances.get(&key).unwrap_or_else(|| env::panic_str("No allowance"));
+        let amount: Balance = amount.into();
+        allowance.amount = allowance
+            .available()
+            .checked_sub(amount)
+            .unwrap_or_else(|| env::panic_str("Amount exceeds allowance"));
+        if allowance.amount > 0 {
+            self.allowances.insert(&key, &allowance);
+        } else {
+            self.internal_remove_allowance(&key);
+        }
+        self.internal_transfer(&owner_id, &receiver_id, amount, memo);
+    }
+}
+
+impl Contract {
+    /// Removes a used up allowance and refunds the storage of its record to the owner.
+    fn internal_remove_allowance(&mut self, key: &(AccountId, AccountId)) {
+        let initial_storage_usage = env::storage_usage();
+        self.allowances.remove(key);
+        let refund =
+            Balance::from(initial_storage_usage - env::storage_usage()) * env::storage_byte_cost();
+        Promise::new(key.0.clone()).transfer(refund);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        contract.ft_approve(accounts(3), (TOTAL_SUPPLY / 2).into(), Some(U64(1_000)));
+        (context, contract)
+    }
+
+    #[test]
+    fn transfer_from() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, TOTAL_SUPPLY / 2);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let transfer_amount = TOTAL_SUPPLY / 3;
+        contract.ft_transfer_from(accounts(2), accounts(1), transfer_amount.into(), None);
+
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - transfer_amount);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
+        assert_eq!(
+            contract.ft_allowance(accounts(2), accounts(3)).0,
+            TOTAL_SUPPLY / 2 - transfer_amount
+        );
+
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 0);
+    }
+
+    #[test]
+    fn used_up_allowance_is_removed() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.ft_transfer_from(accounts(2), accounts(1), (TOTAL_SUPPLY / 2).into(), None);
+        assert!(!contract.allowances.contains_key(&(accounts(2), accounts(3))));
+        assert!(get_created_receipts().iter().any(|receipt| receipt.receiver_id == accounts(2)
+            && matches!(receipt.actions[..], [VmAction::Transfer { deposit }] if deposit > 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Amount exceeds allowance")]
+    fn transfer_from_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.ft_transfer_from(accounts(2), accounts(1), TOTAL_SUPPLY.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "No allowance")]
+    fn revoke_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_revoke(accounts(3));
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 0);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.ft_transfer_from(accounts(2), accounts(1), 1.into(), None);
+    }
+}
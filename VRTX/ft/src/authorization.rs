@@ -0,0 +1,316 @@
+/*!
+Signed off-chain transfer authorizations.
+
+An account registers ed25519 public keys with `register_authorization_key`. A relayer can then
+submit `transfer_with_authorization` on its behalf and pay for the gas. The signature must cover
+the borsh serialization of `TransferAuthorization`:
+
+```text
+domain: String          "vrtx-ft/transfer_with_authorization/v1"
+contract_id: AccountId  this contract
+from: AccountId
+to: AccountId
+amount: u128
+memo: Option<String>
+nonce: u64
+valid_before: u64       block timestamp in nanoseconds
+```
+
+Each `(from, nonce)` pair can only be used once. Delegations signed for `delegate_by_sig` have
+nonces of their own.
+*/
+// `transfer_with_authorization` takes the whole signed message as arguments.
+#![allow(clippy::too_many_arguments)]
+
+use crate::*;
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::{require, CurveType};
+
+pub(crate) const TRANSFER_AUTHORIZATION_DOMAIN: &str = "vrtx-ft/transfer_with_authorization/v1";
+
+#[derive(BorshSerialize)]
+pub struct TransferAuthorization {
+    pub domain: String,
+    pub contract_id: AccountId,
+    pub from: AccountId,
+    pub to: AccountId,
+    pub amount: Balance,
+    pub memo: Option<String>,
+    pub nonce: u64,
+    pub valid_before: u64,
+}
+
+/// Kind of signed message a nonce was used for, so each kind has a nonce space of its own.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorizationKind {
+    Transfer,
+    Delegation,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct AuthorizationUsed<'a> {
+    authorizer_id: &'a AccountId,
+    kind: AuthorizationKind,
+    nonce: U64,
+}
+
+/// Checks the signature with the `ed25519_verify` host function, which near-sdk 4 has no `env`
+/// wrapper for.
+fn ed25519_verify(signature: &[u8], message: &[u8], public_key: &[u8]) -> bool {
+    if signature.len() != 64 || public_key.len() != 32 {
+        return false;
+    }
+    unsafe {
+        near_sys::ed25519_verify(
+            signature.len() as u64,
+            signature.as_ptr() as u64,
+            message.len() as u64,
+            message.as_ptr() as u64,
+            public_key.len() as u64,
+            public_key.as_ptr() as u64,
+        ) == 1
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers an ed25519 `public_key` that can sign authorizations for the caller.
+    #[payable]
+    pub fn register_authorization_key(&mut self, public_key: PublicKey) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(public_key.curve_type() == CurveType::ED25519, "Only ed25519 keys are supported");
+        let initial_storage_usage = env::storage_usage();
+        self.authorization_keys.insert(&(env::predecessor_account_id(), public_key));
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    #[payable]
+    pub fn remove_authorization_key(&mut self, public_key: PublicKey) {
+        assert_one_yocto();
+        let initial_storage_usage = env::storage_usage();
+        require!(
+            self.authorization_keys.remove(&(env::predecessor_account_id(), public_key)),
+            "The key is not registered"
+        );
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    pub fn has_authorization_key(&self, account_id: AccountId, public_key: PublicKey) -> bool {
+        self.authorization_keys.contains(&(account_id, public_key))
+    }
+
+    pub fn is_authorization_used(&self, account_id: AccountId, nonce: U64) -> bool {
+        self.used_authorizations.contains(&(account_id, AuthorizationKind::Transfer, nonce.0))
+    }
+
+    /// Transfers `amount` from `from` to `to` with a signature by one of the keys `from`
+    /// registered, so the caller pays the gas instead of the token holder.
+    #[payable]
+    pub fn transfer_with_authorization(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        nonce: U64,
+        valid_before: U64,
+        public_key: PublicKey,
+        signature: Base64VecU8,
+    ) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(env::block_timestamp() < valid_before.0, "The authorization has expired");
+
+        let message = TransferAuthorization {
+            domain: TRANSFER_AUTHORIZATION_DOMAIN.to_string(),
+            contract_id: env::current_account_id(),
+            from: from.clone(),
+            to: to.clone(),
+            amount: amount.into(),
+            memo: memo.clone(),
+            nonce: nonce.0,
+            valid_before: valid_before.0,
+        };
+        let initial_storage_usage = env::storage_usage();
+        self.internal_use_authorization(
+            &from,
+            AuthorizationKind::Transfer,
+            nonce.0,
+            &public_key,
+            &message.try_to_vec().unwrap(),
+            &signature.0,
+        );
+        self.internal_transfer(&from, &to, amount.into(), memo);
+        self.internal_settle_storage(initial_storage_usage);
+    }
+}
+
+impl Contract {
+    /// Checks that `signature` over `message` was made by a key registered to `account_id` and
+    /// marks `nonce` as used for the `kind` of the message.
+    pub(crate) fn internal_use_authorization(
+        &mut self,
+        account_id: &AccountId,
+        kind: AuthorizationKind,
+        nonce: u64,
+        public_key: &PublicKey,
+        message: &[u8],
+        signature: &[u8],
+    ) {
+        require!(
+            self.authorization_keys.contains(&(account_id.clone(), public_key.clone())),
+            "The key is not registered to the account"
+        );
+        require!(
+            ed25519_verify(signature, message, &public_key.as_bytes()[1..]),
+            "Invalid signature"
+        );
+        require!(
+            self.used_authorizations.insert(&(account_id.clone(), kind, nonce)),
+            "The authorization is already used"
+        );
+        emit_nep297_event(
+            "authorization_used",
+            &[AuthorizationUsed { authorizer_id: account_id, kind, nonce: nonce.into() }],
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    /// The mocked blockchain of near-sdk 4 doesn't implement the `ed25519_verify` host function,
+    /// so the tests link this one in its place.
+    #[no_mangle]
+    extern "C" fn ed25519_verify(
+        signature_len: u64,
+        signature_ptr: u64,
+        message_len: u64,
+        message_ptr: u64,
+        public_key_len: u64,
+        public_key_ptr: u64,
+    ) -> u64 {
+        use ed25519_dalek::Verifier;
+        let (signature, message, public_key) = unsafe {
+            (
+                std::slice::from_raw_parts(signature_ptr as *const u8, signature_len as usize),
+                std::slice::from_raw_parts(message_ptr as *const u8, message_len as usize),
+                std::slice::from_raw_parts(public_key_ptr as *const u8, public_key_len as usize),
+            )
+        };
+        let signature = ed25519_dalek::Signature::try_from(signature).unwrap();
+        let public_key = ed25519_dalek::PublicKey::from_bytes(public_key).unwrap();
+        public_key.verify(message, &signature).is_ok() as u64
+    }
+
+    pub(crate) fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    pub(crate) fn near_public_key(keypair: &Keypair) -> PublicKey {
+        let mut data = vec![CurveType::ED25519 as u8];
+        data.extend_from_slice(keypair.public.as_bytes());
+        PublicKey::try_from(data).unwrap()
+    }
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        contract.register_authorization_key(near_public_key(&keypair()));
+        (context, contract)
+    }
+
+    fn sign(amount: Balance, nonce: u64) -> Base64VecU8 {
+        let message = TransferAuthorization {
+            domain: TRANSFER_AUTHORIZATION_DOMAIN.to_string(),
+            contract_id: accounts(0),
+            from: accounts(2),
+            to: accounts(1),
+            amount,
+            memo: None,
+            nonce,
+            valid_before: 1_000,
+        };
+        keypair().sign(&message.try_to_vec().unwrap()).to_bytes().to_vec().into()
+    }
+
+    #[test]
+    fn transfer_with_authorization() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let transfer_amount = TOTAL_SUPPLY / 3;
+        contract.transfer_with_authorization(
+            accounts(2),
+            accounts(1),
+            transfer_amount.into(),
+            None,
+            U64(1),
+            U64(1_000),
+            near_public_key(&keypair()),
+            sign(transfer_amount, 1),
+        );
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - transfer_amount);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
+        assert!(contract.is_authorization_used(accounts(2), U64(1)));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"authorization_used\"")
+            && log.contains("\"kind\":\"transfer\"")));
+    }
+
+    #[test]
+    #[should_panic(expected = "The authorization is already used")]
+    fn transfer_with_authorization_replay_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT)
+            .predecessor_account_id(accounts(3))
+            .build());
+        for _ in 0..2 {
+            contract.transfer_with_authorization(
+                accounts(2),
+                accounts(1),
+                1.into(),
+                None,
+                U64(1),
+                U64(1_000),
+                near_public_key(&keypair()),
+                sign(1, 1),
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn transfer_with_authorization_signature_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(DEPOSIT)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.transfer_with_authorization(
+            accounts(2),
+            accounts(1),
+            2.into(),
+            None,
+            U64(1),
+            U64(1_000),
+            near_public_key(&keypair()),
+            sign(1, 1),
+        );
+    }
+}
@@ -0,0 +1,273 @@
+/*!
+Circulating supply.
+
+The owner can exclude accounts such as the treasury or team wallets from the circulating supply,
+with a short reason for each. `ft_circulating_supply` is the total supply minus the balances of
+the excluded accounts, of the sink account and of the contract account, which holds the tokens
+locked in vesting schedules, streams, escrows, HTLCs, airdrops and claimable balances.
+
+The contract keeps a running total of the tokens locked in each of these, so
+`get_circulating_supply_breakdown` reports them separately. Tokens transferred to the contract
+account directly are reported on their own as well.
+*/
+use crate::*;
+
+const MAX_EXCLUDED_ACCOUNTS: usize = 20;
+const MAX_REASON_LENGTH: usize = 64;
+
+/// What tokens held by the contract account are locked in.
+#[derive(Clone, Copy)]
+pub(crate) enum Lock {
+    Vesting,
+    Stream,
+    Escrow,
+    Htlc,
+    Airdrop,
+    ClaimableBalance,
+}
+
+/// Tokens held by the contract account, by what they are locked in.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct LockedSupply {
+    vesting: Balance,
+    streams: Balance,
+    escrows: Balance,
+    htlcs: Balance,
+    airdrops: Balance,
+    claimable_balances: Balance,
+}
+
+impl LockedSupply {
+    fn balance_mut(&mut self, lock: Lock) -> &mut Balance {
+        match lock {
+            Lock::Vesting => &mut self.vesting,
+            Lock::Stream => &mut self.streams,
+            Lock::Escrow => &mut self.escrows,
+            Lock::Htlc => &mut self.htlcs,
+            Lock::Airdrop => &mut self.airdrops,
+            Lock::ClaimableBalance => &mut self.claimable_balances,
+        }
+    }
+
+    fn total(&self) -> Balance {
+        self.vesting
+            + self.streams
+            + self.escrows
+            + self.htlcs
+            + self.airdrops
+            + self.claimable_balances
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ExcludedAccount {
+    account_id: AccountId,
+    reason: String,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExcludedBalance {
+    account_id: AccountId,
+    reason: String,
+    balance: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CirculatingSupplyBreakdown {
+    total_supply: U128,
+    circulating_supply: U128,
+    vesting: U128,
+    streams: U128,
+    escrows: U128,
+    htlcs: U128,
+    airdrops: U128,
+    claimable_balances: U128,
+    /// Tokens of the contract account that aren't locked in any of the above, such as tokens
+    /// transferred to it directly.
+    other_contract_balance: U128,
+    /// Balance of the sink account, which holds tokens that would otherwise have been burned.
+    sink: U128,
+    excluded: Vec<ExcludedBalance>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SupplyExclusionSet<'a> {
+    account_id: &'a AccountId,
+    reason: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SupplyExclusionRemoved<'a> {
+    account_id: &'a AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Excludes `account_id` from the circulating supply, or changes the reason of an excluded
+    /// account.
+    #[payable]
+    pub fn exclude_from_circulating_supply(&mut self, account_id: AccountId, reason: String) {
+        self.only_owner();
+        assert_one_yocto();
+        require!(
+            account_id != env::current_account_id(),
+            "The contract account is always excluded"
+        );
+        require!(reason.len() <= MAX_REASON_LENGTH, "Reason must have at most 64 bytes");
+        match self.excluded_accounts.iter_mut().find(|excluded| excluded.account_id == account_id) {
+            Some(excluded) => excluded.reason = reason.clone(),
+            None => {
+                require!(
+                    self.excluded_accounts.len() < MAX_EXCLUDED_ACCOUNTS,
+                    "At most 20 accounts can be excluded"
+                );
+                self.excluded_accounts.push(ExcludedAccount {
+                    account_id: account_id.clone(),
+                    reason: reason.clone(),
+                });
+            }
+        }
+        emit_nep297_event(
+            "supply_exclusion_set",
+            &[SupplyExclusionSet { account_id: &account_id, reason: &reason }],
+        );
+    }
+
+    #[payable]
+    pub fn include_in_circulating_supply(&mut self, account_id: AccountId) {
+        self.only_owner();
+        assert_one_yocto();
+        let count = self.excluded_accounts.len();
+        self.excluded_accounts.retain(|excluded| excluded.account_id != account_id);
+        require!(self.excluded_accounts.len() < count, "Account is not excluded");
+        emit_nep297_event(
+            "supply_exclusion_removed",
+            &[SupplyExclusionRemoved { account_id: &account_id }],
+        );
+    }
+
+    pub fn ft_circulating_supply(&self) -> U128 {
+        self.get_circulating_supply_breakdown().circulating_supply
+    }
+
+    pub fn get_circulating_supply_breakdown(&self) -> CirculatingSupplyBreakdown {
+        let total_supply = self.ft_total_supply();
+        let locked = &self.locked_supply;
+        let contract_id = env::current_account_id();
+        let contract_balance = self.ft_balance_of(contract_id.clone()).0;
+        let sink_id = self.sink_account.as_ref().filter(|sink_id| *sink_id != &contract_id);
+        let sink = sink_id.map_or(0, |sink_id| self.ft_balance_of(sink_id.clone()).0);
+        let excluded: Vec<ExcludedBalance> = self
+            .excluded_accounts
+            .iter()
+            .filter(|excluded| Some(&excluded.account_id) != sink_id)
+            .map(|excluded| ExcludedBalance {
+                account_id: excluded.account_id.clone(),
+                reason: excluded.reason.clone(),
+                balance: self.ft_balance_of(excluded.account_id.clone()),
+            })
+            .collect();
+        let excluded_total: Balance = excluded.iter().map(|excluded| excluded.balance.0).sum();
+        CirculatingSupplyBreakdown {
+            total_supply,
+            circulating_supply: (total_supply.0 - contract_balance - sink - excluded_total).into(),
+            vesting: locked.vesting.into(),
+            streams: locked.streams.into(),
+            escrows: locked.escrows.into(),
+            htlcs: locked.htlcs.into(),
+            airdrops: locked.airdrops.into(),
+            claimable_balances: locked.claimable_balances.into(),
+            other_contract_balance: contract_balance.saturating_sub(locked.total()).into(),
+            sink: sink.into(),
+            excluded,
+        }
+    }
+}
+
+impl Contract {
+    /// Adds `amount` that was moved to the contract account to the tokens locked in `lock`.
+    pub(crate) fn internal_lock(&mut self, lock: Lock, amount: Balance) {
+        *self.locked_supply.balance_mut(lock) += amount;
+    }
+
+    /// Removes `amount` that is leaving the contract account from the tokens locked in `lock`.
+    pub(crate) fn internal_unlock(&mut self, lock: Lock, amount: Balance) {
+        *self.locked_supply.balance_mut(lock) -= amount;
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        setup_with(&[accounts(1)], ONE_YOCTO)
+    }
+
+    #[test]
+    fn circulating_supply() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 1_000.into(), None);
+        contract.exclude_from_circulating_supply(accounts(1), "treasury".to_string());
+        assert_eq!(contract.ft_circulating_supply().0, TOTAL_SUPPLY - 1_000);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(DEPOSIT).build());
+        contract.create_vesting(accounts(3), 500.into(), 0.into(), 0.into(), 100.into(), false);
+        let breakdown = contract.get_circulating_supply_breakdown();
+        assert_eq!(breakdown.circulating_supply.0, TOTAL_SUPPLY - 1_500);
+        assert_eq!(breakdown.vesting.0, 500);
+        assert_eq!(breakdown.other_contract_balance.0, 0);
+        assert_eq!(breakdown.excluded[0].reason, "treasury");
+        assert_eq!(breakdown.excluded[0].balance.0, 1_000);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.include_in_circulating_supply(accounts(1));
+        assert_eq!(contract.ft_circulating_supply().0, TOTAL_SUPPLY - 500);
+    }
+
+    #[test]
+    fn circulating_supply_components() {
+        let (mut context, mut contract) = setup();
+        contract.set_sink_account(Some(accounts(1)));
+        contract.ft_transfer(accounts(1), 300.into(), None);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(DEPOSIT).build());
+        let vesting_id =
+            contract.create_vesting(accounts(3), 500.into(), 0.into(), 0.into(), 100.into(), true);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        contract.ft_transfer(env::current_account_id(), 200.into(), None);
+        let breakdown = contract.get_circulating_supply_breakdown();
+        assert_eq!(breakdown.circulating_supply.0, TOTAL_SUPPLY - 1_000);
+        assert_eq!(breakdown.vesting.0, 500);
+        assert_eq!(breakdown.streams.0, 0);
+        assert_eq!(breakdown.other_contract_balance.0, 200);
+        assert_eq!(breakdown.sink.0, 300);
+
+        contract.revoke(vesting_id);
+        let breakdown = contract.get_circulating_supply_breakdown();
+        assert_eq!(breakdown.circulating_supply.0, TOTAL_SUPPLY - 500);
+        assert_eq!(breakdown.vesting.0, 0);
+        assert_eq!(breakdown.other_contract_balance.0, 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is not excluded")]
+    fn include_in_circulating_supply_fail() {
+        let (_, mut contract) = setup();
+        contract.include_in_circulating_supply(accounts(1));
+    }
+}
@@ -0,0 +1,305 @@
+/*!
+Claimable balances for receivers that have not registered yet.
+
+`send_or_escrow` transfers directly when the receiver is registered. Otherwise the tokens are held
+by the contract account as a claimable balance. Once the receiver registers with
+`storage_deposit`, `claim` pays out everything it was sent. If the receiver doesn't claim within
+`CLAIMABLE_BALANCE_DURATION`, the sender can `reclaim` the tokens.
+
+A receiver can hold claimable balances from up to `MAX_CLAIMABLE_BALANCES_PER_ACCOUNT` senders.
+So that dust can't take them up, a sender's first balance for a receiver must hold at least
+`min_claimable_amount`, which starts at one whole token and is set by the owner.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::U64;
+
+const CLAIMABLE_BALANCE_DURATION: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_CLAIMABLE_BALANCES_PER_ACCOUNT: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ClaimableBalance {
+    sender_id: AccountId,
+    amount: Balance,
+    expires_at: u64,
+    storage_cost: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimableBalanceView {
+    sender_id: AccountId,
+    amount: U128,
+    expires_at: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct ClaimableBalanceEvent<'a> {
+    sender_id: &'a AccountId,
+    receiver_id: &'a AccountId,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct MinClaimableAmountSet {
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers `amount` to `receiver_id`, or keeps it claimable for the receiver if it is not
+    /// registered.
+    #[payable]
+    pub fn send_or_escrow(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let sender_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        if self.token.accounts.contains_key(&receiver_id) {
+            self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+        } else {
+            self.internal_escrow_claimable(sender_id, receiver_id, amount.into());
+        }
+        self.internal_settle_storage(initial_storage_usage);
+    }
+
+    /// Pays out all balances claimable by the caller, which must be registered.
+    #[payable]
+    pub fn claim(&mut self) -> U128 {
+        assert_one_yocto();
+        let receiver_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&receiver_id);
+        let balances = self
+            .claimable_balances
+            .remove(&receiver_id)
+            .unwrap_or_else(|| env::panic_str("Nothing to claim"));
+
+        let mut claimed = 0;
+        for balance in balances {
+            self.internal_release_escrow(
+                Lock::ClaimableBalance,
+                &receiver_id,
+                balance.amount,
+                Some("claim".to_string()),
+            );
+            claimed += balance.amount;
+            emit_nep297_event(
+                "claimable_balance_claimed",
+                &[ClaimableBalanceEvent {
+                    sender_id: &balance.sender_id,
+                    receiver_id: &receiver_id,
+                    amount: balance.amount.into(),
+                }],
+            );
+            if balance.storage_cost > 0 {
+                Promise::new(balance.sender_id).transfer(balance.storage_cost);
+            }
+        }
+        claimed.into()
+    }
+
+    /// Returns the tokens the caller sent to `receiver_id` once they can no longer be claimed.
+    #[payable]
+    pub fn reclaim(&mut self, receiver_id: AccountId) -> U128 {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        let mut balances = self.claimable_balances.get(&receiver_id).unwrap_or_default();
+        let index = balances
+            .iter()
+            .position(|b| b.sender_id == sender_id)
+            .unwrap_or_else(|| env::panic_str("Nothing to reclaim"));
+        require!(
+            balances[index].expires_at <= env::block_timestamp(),
+            "The balance can still be claimed"
+        );
+        let balance = balances.swap_remove(index);
+        if balances.is_empty() {
+            self.claimable_balances.remove(&receiver_id);
+        } else {
+            self.claimable_balances.insert(&receiver_id, &balances);
+        }
+
+        self.internal_release_escrow(
+            Lock::ClaimableBalance,
+            &sender_id,
+            balance.amount,
+            Some("reclaim".to_string()),
+        );
+        emit_nep297_event(
+            "claimable_balance_reclaimed",
+            &[ClaimableBalanceEvent {
+                sender_id: &sender_id,
+                receiver_id: &receiver_id,
+                amount: balance.amount.into(),
+            }],
+        );
+        if balance.storage_cost > 0 {
+            Promise::new(sender_id).transfer(balance.storage_cost);
+        }
+        balance.amount.into()
+    }
+
+    /// Sets the smallest amount a sender can start a claimable balance for a receiver with.
+    #[payable]
+    pub fn set_min_claimable_amount(&mut self, amount: U128) {
+        self.only_owner();
+        assert_one_yocto();
+        self.min_claimable_amount = amount.into();
+        emit_nep297_event("min_claimable_amount_set", &[MinClaimableAmountSet { amount }]);
+    }
+
+    pub fn get_min_claimable_amount(&self) -> U128 {
+        self.min_claimable_amount.into()
+    }
+
+    pub fn get_claimable_balances(&self, account_id: AccountId) -> Vec<ClaimableBalanceView> {
+        let balances = self.claimable_balances.get(&account_id).unwrap_or_default();
+        balances
+            .into_iter()
+            .map(|b| ClaimableBalanceView {
+                sender_id: b.sender_id,
+                amount: b.amount.into(),
+                expires_at: b.expires_at.into(),
+            })
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Returns the total that `account_id` can claim.
+    pub(crate) fn internal_claimable_amount(&self, account_id: &AccountId) -> Balance {
+        let balances = self.claimable_balances.get(account_id).unwrap_or_default();
+        balances.iter().map(|b| b.amount).sum()
+    }
+
+    /// Locks `amount` as claimable by `receiver_id`, on top of what `sender_id` already sent it.
+    /// The expiry restarts with each addition.
+    fn internal_escrow_claimable(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+    ) {
+        self.internal_lock_escrow(Lock::ClaimableBalance, &sender_id, amount, "claimable balance");
+        let initial_storage_usage = env::storage_usage();
+        let mut balances = self.claimable_balances.get(&receiver_id).unwrap_or_default();
+        let expires_at = env::block_timestamp() + CLAIMABLE_BALANCE_DURATION;
+        let index = match balances.iter().position(|b| b.sender_id == sender_id) {
+            Some(index) => {
+                balances[index].amount += amount;
+                balances[index].expires_at = expires_at;
+                index
+            }
+            None => {
+                require!(
+                    amount >= self.min_claimable_amount,
+                    "The amount is less than the minimum claimable amount"
+                );
+                require!(
+                    balances.len() < MAX_CLAIMABLE_BALANCES_PER_ACCOUNT,
+                    "Too many claimable balances for the receiver"
+                );
+                balances.push(ClaimableBalance {
+                    sender_id: sender_id.clone(),
+                    amount,
+                    expires_at,
+                    storage_cost: 0,
+                });
+                balances.len() - 1
+            }
+        };
+        self.claimable_balances.insert(&receiver_id, &balances);
+        // Only a new entry takes more storage, and its size doesn't depend on the recorded cost.
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        if storage_cost > 0 {
+            balances[index].storage_cost = storage_cost;
+            self.claimable_balances.insert(&receiver_id, &balances);
+        }
+        emit_nep297_event(
+            "claimable_balance_created",
+            &[ClaimableBalanceEvent {
+                sender_id: &sender_id,
+                receiver_id: &receiver_id,
+                amount: amount.into(),
+            }],
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{register, setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[], ONE_YOCTO);
+        // The test token has less than one whole token.
+        contract.set_min_claimable_amount((TOTAL_SUPPLY / 100).into());
+        testing_env!(context.attached_deposit(DEPOSIT).build());
+        contract.send_or_escrow(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn claim_after_registration() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY * 3 / 4);
+        assert_eq!(contract.get_claimable_balances(accounts(1))[0].amount.0, TOTAL_SUPPLY / 4);
+
+        register(&mut context, &mut contract, &[accounts(1)]);
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        assert_eq!(contract.claim().0, TOTAL_SUPPLY / 4);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY / 4);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+        assert!(contract.get_claimable_balances(accounts(1)).is_empty());
+
+        // Registered receivers get the tokens right away.
+        testing_env!(context.attached_deposit(DEPOSIT).predecessor_account_id(accounts(2)).build());
+        contract.send_or_escrow(accounts(1), (TOTAL_SUPPLY / 4).into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY / 2);
+    }
+
+    #[test]
+    fn reclaim_after_expiry() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .block_timestamp(CLAIMABLE_BALANCE_DURATION)
+            .build());
+        assert_eq!(contract.reclaim(accounts(1)).0, TOTAL_SUPPLY / 4);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        assert!(contract.get_claimable_balances(accounts(1)).is_empty());
+    }
+
+    #[test]
+    fn top_up_below_minimum() {
+        let (_, mut contract) = setup();
+        contract.send_or_escrow(accounts(1), 1.into(), None);
+        assert_eq!(contract.get_claimable_balances(accounts(1))[0].amount.0, TOTAL_SUPPLY / 4 + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount is less than the minimum claimable amount")]
+    fn send_dust_fail() {
+        let (_, mut contract) = setup();
+        contract.send_or_escrow(accounts(3), (TOTAL_SUPPLY / 100 - 1).into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The balance can still be claimed")]
+    fn reclaim_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .block_timestamp(CLAIMABLE_BALANCE_DURATION - 1)
+            .build());
+        contract.reclaim(accounts(1));
+    }
+}
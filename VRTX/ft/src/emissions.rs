@@ -0,0 +1,251 @@
+/*!
+Scheduled emissions to a treasury account.
+
+The emission rate is either a share of the total supply per year or a fixed amount per second.
+Every `epoch_duration` nanoseconds since the schedule was set, the rate decays by `decay_bps`.
+Emissions accrue from the last mint, and anyone can call `mint_emissions` to mint them to the
+treasury. The total supply never grows past `cap`.
+*/
+use crate::*;
+use near_contract_standards::fungible_token::events::FtMint;
+use near_sdk::json_types::U64;
+use near_sdk::serde::Deserialize;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
+const DECAY_SCALE: u128 = 1_000_000_000_000_000_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EmissionRate {
+    /// Share of the total supply emitted per year, in basis points.
+    AnnualBps(u16),
+    PerSecond(U128),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmissionSchedule {
+    pub treasury_id: AccountId,
+    pub rate: EmissionRate,
+    pub decay_bps: u16,
+    pub epoch_duration: U64,
+    pub cap: U128,
+}
+
+impl EmissionRate {
+    /// Returns the amount emitted per year at this rate before decay, or `None` on overflow.
+    fn annual_amount(&self, total_supply: Balance) -> Option<Balance> {
+        match self {
+            EmissionRate::AnnualBps(bps) => {
+                Some(mul_div(total_supply, (*bps).into(), MAX_BPS.into()))
+            }
+            EmissionRate::PerSecond(amount) => amount.0.checked_mul(SECONDS_PER_YEAR),
+        }
+    }
+}
+
+/// Returns what `annual_amount` per year accrues over `elapsed` nanoseconds.
+fn accrued_amount(annual_amount: Balance, elapsed: u64) -> Balance {
+    mul_div(annual_amount, elapsed.into(), SECONDS_PER_YEAR * NANOS_PER_SECOND)
+}
+
+/// Returns `(1 - decay_bps) ^ epochs`, scaled by `DECAY_SCALE`.
+fn decay_factor(decay_bps: u16, mut epochs: u64) -> u128 {
+    let mut base = DECAY_SCALE / u128::from(MAX_BPS) * u128::from(MAX_BPS - decay_bps);
+    let mut factor = DECAY_SCALE;
+    while epochs > 0 && factor > 0 {
+        if epochs & 1 == 1 {
+            factor = mul_div(factor, base, DECAY_SCALE);
+        }
+        base = mul_div(base, base, DECAY_SCALE);
+        epochs >>= 1;
+    }
+    factor
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces the emission schedule, or stops emissions with `None`. What accrued under the
+    /// previous schedule is minted first.
+    #[payable]
+    pub fn set_emission_schedule(&mut self, schedule: Option<EmissionSchedule>) {
+        self.only_owner();
+        assert_one_yocto();
+        if self.emission_schedule.is_some() {
+            self.mint_emissions();
+        }
+        if let Some(schedule) = &schedule {
+            require!(schedule.decay_bps < MAX_BPS, "Decay must be lower than 10000 basis points");
+            require!(
+                schedule.decay_bps == 0 || schedule.epoch_duration.0 > 0,
+                "Decay requires an epoch duration"
+            );
+            require!(
+                schedule.rate.annual_amount(self.ft_total_supply().0).is_some(),
+                "Emission rate is too high"
+            );
+            self.token.internal_unwrap_balance_of(&schedule.treasury_id);
+        }
+        emit_nep297_event("emission_schedule_set", &[&schedule]);
+        self.emission_schedule = schedule;
+        self.emission_start = env::block_timestamp();
+        self.emission_last_mint = env::block_timestamp();
+    }
+
+    pub fn get_emission_schedule(&self) -> Option<EmissionSchedule> {
+        self.emission_schedule.clone()
+    }
+
+    /// Returns the emissions that accrued since the last mint.
+    pub fn accrued_emissions(&self) -> U128 {
+        self.internal_accrued_emissions().into()
+    }
+
+    /// Mints the accrued emissions to the treasury and returns the minted amount.
+    pub fn mint_emissions(&mut self) -> U128 {
+        let schedule = self
+            .emission_schedule
+            .clone()
+            .unwrap_or_else(|| env::panic_str("No emission schedule"));
+        let amount = self.internal_accrued_emissions();
+        self.emission_last_mint = env::block_timestamp();
+        if amount > 0 {
+            self.internal_deposit(&schedule.treasury_id, amount);
+            FtMint {
+                owner_id: &schedule.treasury_id,
+                amount: &U128(amount),
+                memo: Some("emissions"),
+            }
+            .emit();
+        }
+        amount.into()
+    }
+}
+
+impl Contract {
+    fn internal_accrued_emissions(&self) -> Balance {
+        let schedule = match &self.emission_schedule {
+            Some(schedule) => schedule,
+            None => return 0,
+        };
+        let total_supply = self.ft_total_supply().0;
+        let annual_amount = schedule
+            .rate
+            .annual_amount(total_supply)
+            .unwrap_or_else(|| env::panic_str("Emission rate is too high"));
+        let now = env::block_timestamp();
+        let last_mint = self.emission_last_mint;
+        let accrued = if schedule.decay_bps == 0 {
+            accrued_amount(annual_amount, now - last_mint)
+        } else {
+            // Sums the partial epoch of the last mint, the full epochs in between and the
+            // partial epoch of now, without going through the epochs one by one.
+            let epoch_duration = schedule.epoch_duration.0;
+            let epoch_start = |epoch: u64| self.emission_start + epoch * epoch_duration;
+            let rate = |epoch: u64| {
+                mul_div(annual_amount, decay_factor(schedule.decay_bps, epoch), DECAY_SCALE)
+            };
+            let first_epoch = (last_mint - self.emission_start) / epoch_duration;
+            let last_epoch = (now - self.emission_start) / epoch_duration;
+            if first_epoch == last_epoch {
+                accrued_amount(rate(first_epoch), now - last_mint)
+            } else {
+                let full_epochs = last_epoch - first_epoch - 1;
+                // `1 + r + ... + r^(n - 1) = (1 - r^n) / (1 - r)` with `r = 1 - decay_bps`.
+                let series = mul_div(
+                    DECAY_SCALE - decay_factor(schedule.decay_bps, full_epochs),
+                    MAX_BPS.into(),
+                    schedule.decay_bps.into(),
+                );
+                accrued_amount(rate(first_epoch), epoch_start(first_epoch + 1) - last_mint)
+                    + accrued_amount(
+                        mul_div(rate(first_epoch + 1), series, DECAY_SCALE),
+                        epoch_duration,
+                    )
+                    + accrued_amount(rate(last_epoch), now - epoch_start(last_epoch))
+            }
+        };
+        accrued.min(schedule.cap.0.saturating_sub(total_supply))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    const SECOND: u64 = 1_000_000_000;
+    const RATE: Balance = 1_000;
+
+    fn setup(rate: EmissionRate, decay_bps: u16, cap: Balance) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], ONE_YOCTO);
+        testing_env!(context.block_timestamp(100 * SECOND).build());
+        contract.set_emission_schedule(Some(EmissionSchedule {
+            treasury_id: accounts(1),
+            rate,
+            decay_bps,
+            epoch_duration: U64(10 * SECOND),
+            cap: cap.into(),
+        }));
+        (context, contract)
+    }
+
+    #[test]
+    fn emissions_decay_per_epoch() {
+        let (mut context, mut contract) =
+            setup(EmissionRate::PerSecond(RATE.into()), 5_000, TOTAL_SUPPLY * 2);
+        testing_env!(context.block_timestamp(110 * SECOND).build());
+        assert_eq!(contract.accrued_emissions().0, 10 * RATE);
+        testing_env!(context.block_timestamp(125 * SECOND).build());
+        assert_eq!(contract.accrued_emissions().0, 10 * RATE + 5 * RATE + 5 * RATE / 4);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.mint_emissions().0, 10 * RATE + 5 * RATE + 5 * RATE / 4);
+        assert_eq!(contract.accrued_emissions().0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 10 * RATE + 5 * RATE + 5 * RATE / 4);
+
+        testing_env!(context.block_timestamp(140 * SECOND).build());
+        assert_eq!(contract.accrued_emissions().0, 5 * RATE / 4 + 10 * RATE / 8);
+    }
+
+    #[test]
+    fn emissions_stop_at_cap() {
+        let (mut context, mut contract) =
+            setup(EmissionRate::AnnualBps(1_000), 0, TOTAL_SUPPLY + TOTAL_SUPPLY / 20);
+        testing_env!(context.block_timestamp(100 * SECOND + 365 * 24 * 3600 * SECOND / 4).build());
+        assert_eq!(contract.accrued_emissions().0, TOTAL_SUPPLY / 40);
+        testing_env!(context.block_timestamp(100 * SECOND + 365 * 24 * 3600 * SECOND).build());
+        assert_eq!(contract.accrued_emissions().0, TOTAL_SUPPLY / 20);
+        contract.mint_emissions();
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY + TOTAL_SUPPLY / 20);
+
+        testing_env!(context.block_timestamp(200 * SECOND + 365 * 24 * 3600 * SECOND).build());
+        assert_eq!(contract.mint_emissions().0, 0);
+    }
+
+    #[test]
+    fn emissions_over_many_epochs() {
+        let (mut context, contract) =
+            setup(EmissionRate::PerSecond(RATE.into()), 5_000, TOTAL_SUPPLY * 2);
+        testing_env!(context.block_timestamp(100 * SECOND + 1_000_000_000 * 10 * SECOND).build());
+        assert_eq!(contract.accrued_emissions().0, 20 * RATE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Emission rate is too high")]
+    fn set_emission_schedule_fail() {
+        setup(EmissionRate::PerSecond(u128::MAX.into()), 0, TOTAL_SUPPLY * 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "No emission schedule")]
+    fn mint_emissions_fail() {
+        let (_, mut contract) = setup(EmissionRate::PerSecond(RATE.into()), 0, TOTAL_SUPPLY * 2);
+        contract.set_emission_schedule(None);
+        contract.mint_emissions();
+    }
+}
@@ -15,20 +15,188 @@ NOTES:
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
 */
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
 use near_contract_standards::fungible_token::metadata::{
     FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
 };
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_contract_standards::fungible_token::resolver::{ext_ft_resolver, FungibleTokenResolver};
 use near_contract_standards::fungible_token::FungibleToken;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet};
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue};
+use near_sdk::serde::Serialize;
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault,
+    Promise, PromiseOrValue, PublicKey, StorageUsage,
+};
+
+mod accounts;
+mod airdrop;
+mod allowance;
+mod authorization;
+mod circulating;
+mod claimable;
+mod dividends;
+mod emissions;
+mod escrow;
+mod htlc;
+mod invoice;
+mod migration;
+mod sink;
+mod snapshot;
+mod split;
+mod sponsorship;
+mod storage;
+mod streaming;
+mod subscriptions;
+mod vesting;
+mod votes;
+
+#[allow(clippy::all)]
+mod u256 {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+use u256::U256;
+
+const MAX_BPS: u16 = 10_000;
+
+/// Returns `a * b / c` rounded down, without overflowing on the intermediate product.
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    (U256::from(a) * U256::from(b) / U256::from(c)).as_u128()
+}
+
+/// Open vestings, streams or escrows an account can be a party to, so that nobody can grow the
+/// lists of another account without bound.
+const MAX_ACCOUNT_IDS: usize = 100;
+
+/// Adds `id` to the list of ids of each of `account_ids` in `lists`.
+fn push_account_ids(
+    lists: &mut LookupMap<AccountId, Vec<u64>>,
+    account_ids: &[&AccountId],
+    id: u64,
+) {
+    for account_id in account_ids {
+        let mut ids = lists.get(account_id).unwrap_or_default();
+        if ids.len() >= MAX_ACCOUNT_IDS {
+            env::panic_str(&format!(
+                "The account {} is already a party to {} open entries",
+                account_id, MAX_ACCOUNT_IDS
+            ));
+        }
+        ids.push(id);
+        lists.insert(account_id, &ids);
+    }
+}
+
+/// Removes `id` from the list of ids of each of `account_ids` in `lists`, and the lists that
+/// become empty.
+fn remove_account_ids(
+    lists: &mut LookupMap<AccountId, Vec<u64>>,
+    account_ids: &[&AccountId],
+    id: u64,
+) {
+    for account_id in account_ids {
+        let mut ids = lists.get(account_id).unwrap_or_default();
+        ids.retain(|other_id| *other_id != id);
+        if ids.is_empty() {
+            lists.remove(account_id);
+        } else {
+            lists.insert(account_id, &ids);
+        }
+    }
+}
+
+/// Returns up to `limit` ids from the list of `account_id` in `lists`, starting at `from_index`.
+fn account_ids_page(
+    lists: &LookupMap<AccountId, Vec<u64>>,
+    account_id: &AccountId,
+    from_index: Option<u64>,
+    limit: u64,
+) -> Vec<u64> {
+    let ids = lists.get(account_id).unwrap_or_default();
+    ids.into_iter().skip(from_index.unwrap_or(0) as usize).take(limit as usize).collect()
+}
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER.0);
+
+const EVENT_STANDARD: &str = "vrtx-ft";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NearEvent<'a, T: Serialize> {
+    standard: &'static str,
+    version: &'static str,
+    event: &'a str,
+    data: &'a [T],
+}
+
+/// Logs a NEP-297 event for the features this contract adds on top of NEP-141.
+fn emit_nep297_event<T: Serialize>(event: &str, data: &[T]) {
+    let event =
+        NearEvent { standard: EVENT_STANDARD, version: EVENT_STANDARD_VERSION, event, data };
+    let json = near_sdk::serde_json::to_string(&event).unwrap_or_else(|_| env::abort());
+    env::log_str(&format!("EVENT_JSON:{}", json));
+}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     token: FungibleToken,
     metadata: LazyOption<FungibleTokenMetadata>,
+    owner_id: AccountId,
+    allowances: LookupMap<(AccountId, AccountId), allowance::Allowance>,
+    authorization_keys: LookupSet<(AccountId, PublicKey)>,
+    used_authorizations: LookupSet<(AccountId, authorization::AuthorizationKind, u64)>,
+    snapshot_id: u64,
+    account_snapshots: LookupMap<(AccountId, u32), snapshot::Checkpoint>,
+    account_snapshot_counts: LookupMap<AccountId, u32>,
+    total_supply_snapshots: LookupMap<u64, Balance>,
+    delegates: LookupMap<AccountId, AccountId>,
+    vote_checkpoints: LookupMap<(AccountId, u32), votes::VoteCheckpoint>,
+    vote_checkpoint_counts: LookupMap<AccountId, u32>,
+    dividends_per_share: u128,
+    dividend_accounts: LookupMap<AccountId, dividends::DividendAccount>,
+    emission_schedule: Option<emissions::EmissionSchedule>,
+    emission_start: u64,
+    emission_last_mint: u64,
+    claimable_balances: LookupMap<AccountId, Vec<claimable::ClaimableBalance>>,
+    min_claimable_amount: Balance,
+    sponsorship_pool: Balance,
+    sponsors: LookupMap<AccountId, sponsorship::Sponsor>,
+    sponsored_accounts: LookupMap<AccountId, sponsorship::SponsoredAccount>,
+    next_airdrop_id: u64,
+    airdrops: LookupMap<u64, airdrop::Airdrop>,
+    airdrop_claims: LookupMap<(u64, u32), u128>,
+    next_vesting_id: u64,
+    vestings: LookupMap<u64, vesting::Vesting>,
+    account_vestings: LookupMap<AccountId, Vec<u64>>,
+    vesting_balances: LookupMap<AccountId, Balance>,
+    next_stream_id: u64,
+    streams: LookupMap<u64, streaming::Stream>,
+    account_streams: LookupMap<AccountId, Vec<u64>>,
+    next_htlc_id: u64,
+    htlcs: LookupMap<u64, htlc::Htlc>,
+    next_escrow_id: u64,
+    escrows: LookupMap<u64, escrow::Escrow>,
+    account_escrows: LookupMap<AccountId, Vec<u64>>,
+    invoices: LookupMap<(AccountId, String), invoice::Invoice>,
+    next_subscription_id: u64,
+    subscriptions: LookupMap<u64, subscriptions::Subscription>,
+    excluded_accounts: Vec<circulating::ExcludedAccount>,
+    locked_supply: circulating::LockedSupply,
+    sink_account: Option<AccountId>,
+    available_storage: LookupMap<AccountId, Balance>,
+    /// Storage added in the current call that was charged to available storage balances.
+    #[borsh_skip]
+    charged_storage_usage: StorageUsage,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
@@ -57,18 +225,19 @@ impl Contract {
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
     /// the given fungible token metadata.
     #[init]
-    pub fn new(
-        owner_id: AccountId,
-        total_supply: U128,
-        metadata: FungibleTokenMetadata,
-    ) -> Self {
+    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata) -> Self {
         assert!(!env::state_exists(), "Already initialized");
-        assert!(env::predecessor_account_id() == "7f3d1be6c7322ca5a792900b3a42d7131251628b2bbe9e1515e7b4973ede4aee", "Initial Owner ID is invalid");
+        assert!(
+            env::predecessor_account_id().as_str()
+                == "7f3d1be6c7322ca5a792900b3a42d7131251628b2bbe9e1515e7b4973ede4aee",
+            "Initial Owner ID is invalid"
+        );
         metadata.assert_valid();
-        let mut this = Self {
-            token: FungibleToken::new(b"a".to_vec()),
-            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-        };
+        let mut this = Self::with_token(
+            FungibleToken::new(b"a".to_vec()),
+            LazyOption::new(b"m".to_vec(), Some(&metadata)),
+            owner_id.clone(),
+        );
         this.token.internal_register_account(&owner_id);
         this.token.internal_deposit(&owner_id, total_supply.into());
         near_contract_standards::fungible_token::events::FtMint {
@@ -81,16 +250,259 @@ impl Contract {
     }
 
     fn on_account_closed(&mut self, account_id: AccountId, balance: Balance) {
+        self.internal_snapshot_balance(&account_id, balance);
+        self.internal_settle_dividends(&account_id, balance);
+        self.after_balance_change(Some(&account_id), None, balance);
+        if let Some(sink_id) = self.internal_sink_account(&account_id).filter(|_| balance > 0) {
+            self.internal_credit_sink(&account_id, &sink_id, balance, "account closed");
+        }
+        self.internal_refund_available_storage(&account_id);
         log!("Closed @{} with {}", account_id, balance);
     }
 
     fn on_tokens_burned(&mut self, account_id: AccountId, amount: Balance) {
         log!("Account @{} burned {}", account_id, amount);
     }
+
+    fn only_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Only the contract owner can call"
+        );
+    }
 }
 
-near_contract_standards::impl_fungible_token_core!(Contract, token, on_tokens_burned);
-near_contract_standards::impl_fungible_token_storage!(Contract, token, on_account_closed);
+impl Contract {
+    /// Returns a contract around `token` and `metadata`, with everything else empty.
+    fn with_token(
+        token: FungibleToken,
+        metadata: LazyOption<FungibleTokenMetadata>,
+        owner_id: AccountId,
+    ) -> Self {
+        // One whole token.
+        let min_claimable_amount = metadata.get().map_or(0, |metadata| {
+            10u128.checked_pow(metadata.decimals.into()).unwrap_or(Balance::MAX)
+        });
+        Self {
+            token,
+            metadata,
+            owner_id,
+            allowances: LookupMap::new(b"p".to_vec()),
+            authorization_keys: LookupSet::new(b"k".to_vec()),
+            used_authorizations: LookupSet::new(b"n".to_vec()),
+            snapshot_id: 0,
+            account_snapshots: LookupMap::new(b"s".to_vec()),
+            account_snapshot_counts: LookupMap::new(b"C".to_vec()),
+            total_supply_snapshots: LookupMap::new(b"t".to_vec()),
+            delegates: LookupMap::new(b"d".to_vec()),
+            vote_checkpoints: LookupMap::new(b"v".to_vec()),
+            vote_checkpoint_counts: LookupMap::new(b"V".to_vec()),
+            dividends_per_share: 0,
+            dividend_accounts: LookupMap::new(b"i".to_vec()),
+            emission_schedule: None,
+            emission_start: 0,
+            emission_last_mint: 0,
+            claimable_balances: LookupMap::new(b"c".to_vec()),
+            min_claimable_amount,
+            sponsorship_pool: 0,
+            sponsors: LookupMap::new(b"o".to_vec()),
+            sponsored_accounts: LookupMap::new(b"g".to_vec()),
+            next_airdrop_id: 0,
+            airdrops: LookupMap::new(b"e".to_vec()),
+            airdrop_claims: LookupMap::new(b"h".to_vec()),
+            next_vesting_id: 0,
+            vestings: LookupMap::new(b"j".to_vec()),
+            account_vestings: LookupMap::new(b"G".to_vec()),
+            vesting_balances: LookupMap::new(b"B".to_vec()),
+            next_stream_id: 0,
+            streams: LookupMap::new(b"l".to_vec()),
+            account_streams: LookupMap::new(b"u".to_vec()),
+            next_htlc_id: 0,
+            htlcs: LookupMap::new(b"w".to_vec()),
+            next_escrow_id: 0,
+            escrows: LookupMap::new(b"y".to_vec()),
+            account_escrows: LookupMap::new(b"z".to_vec()),
+            invoices: LookupMap::new(b"b".to_vec()),
+            next_subscription_id: 0,
+            subscriptions: LookupMap::new(b"S".to_vec()),
+            excluded_accounts: Vec::new(),
+            locked_supply: Default::default(),
+            sink_account: None,
+            available_storage: LookupMap::new(b"A".to_vec()),
+            charged_storage_usage: 0,
+        }
+    }
+
+    /// Ledger changes go through these wrappers instead of `token` directly, so that the
+    /// bookkeeping derived from balances stays in sync with them.
+    pub(crate) fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.before_balance_change(sender_id);
+        self.before_balance_change(receiver_id);
+        self.token.internal_transfer(sender_id, receiver_id, amount, memo);
+        self.after_balance_change(Some(sender_id), Some(receiver_id), amount);
+    }
+
+    /// Same as `internal_transfer` without the `ft_transfer` event, for callers that emit the
+    /// events of several transfers at once.
+    pub(crate) fn internal_transfer_without_event(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.before_balance_change(sender_id);
+        self.before_balance_change(receiver_id);
+        self.token.internal_withdraw(sender_id, amount);
+        self.token.internal_deposit(receiver_id, amount);
+        self.after_balance_change(Some(sender_id), Some(receiver_id), amount);
+    }
+
+    pub(crate) fn internal_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        self.before_balance_change(account_id);
+        self.token.internal_deposit(account_id, amount);
+        self.after_balance_change(None, Some(account_id), amount);
+    }
+
+    fn before_balance_change(&mut self, account_id: &AccountId) {
+        let balance = self.token.accounts.get(account_id).unwrap_or(0);
+        self.internal_snapshot_balance(account_id, balance);
+        self.internal_settle_dividends(account_id, balance);
+    }
+
+    /// `amount` moved from `from` to `to`, where `None` stands for minted or burned tokens.
+    fn after_balance_change(
+        &mut self,
+        from: Option<&AccountId>,
+        to: Option<&AccountId>,
+        amount: Balance,
+    ) {
+        self.internal_move_delegate_votes(from, to, amount);
+    }
+
+    /// Calls `ft_on_transfer` on the receiver and resolves the transfer afterwards, once the
+    /// tokens of an `ft_transfer_call` have moved.
+    pub(crate) fn internal_notify_receiver(
+        &self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> Promise {
+        ext_ft_receiver::ext(receiver_id.clone())
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                ext_ft_resolver::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .with_unused_gas_weight(0)
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+    }
+
+    /// Charges the predecessor for the storage added since `initial_storage_usage` out of the
+    /// attached deposit, and transfers it what is left, with the cost of any released storage.
+    pub(crate) fn internal_settle_storage(&self, initial_storage_usage: StorageUsage) {
+        let attached_deposit = env::attached_deposit();
+        // Records charged to the accounts they are about are not charged again.
+        let storage_usage = env::storage_usage() - self.charged_storage_usage;
+        let refund = if storage_usage >= initial_storage_usage {
+            let cost =
+                Balance::from(storage_usage - initial_storage_usage) * env::storage_byte_cost();
+            assert!(attached_deposit >= cost, "Must attach {} yoctoNEAR to cover storage", cost);
+            attached_deposit - cost
+        } else {
+            attached_deposit
+                + Balance::from(initial_storage_usage - storage_usage) * env::storage_byte_cost()
+        };
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    /// Moves `amount` from `account_id` to the contract account, which holds it locked in `lock`
+    /// until it is released with `internal_release_escrow`.
+    pub(crate) fn internal_lock_escrow(
+        &mut self,
+        lock: circulating::Lock,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        let contract_id = env::current_account_id();
+        if !self.token.accounts.contains_key(&contract_id) {
+            self.token.internal_register_account(&contract_id);
+        }
+        self.internal_transfer(account_id, &contract_id, amount, Some(memo.to_string()));
+        self.internal_lock(lock, amount);
+    }
+
+    /// Pays `amount` locked in `lock` by the contract account out to `receiver_id`.
+    pub(crate) fn internal_release_escrow(
+        &mut self,
+        lock: circulating::Lock,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.internal_unlock(lock, amount);
+        self.internal_transfer(&env::current_account_id(), receiver_id, amount, memo);
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        if registration_only == Some(true) {
+            return self.token.storage_deposit(Some(account_id), registration_only);
+        }
+        self.internal_storage_deposit(account_id)
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.internal_storage_withdraw(amount)
+    }
+
+    /// Same as `impl_fungible_token_storage!`, except that the storage deposit of a sponsored
+    /// account goes back to the sponsorship pool.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        let closed = match self.sponsored_accounts.remove(&env::predecessor_account_id()) {
+            Some(sponsored) => self.internal_unregister_sponsored(sponsored, force),
+            None => self.token.internal_storage_unregister(force),
+        };
+        if let Some((account_id, balance)) = closed {
+            self.on_account_closed(account_id, balance);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        let available = self.internal_available_storage(&account_id);
+        self.token.storage_balance_of(account_id).map(|storage_balance| StorageBalance {
+            total: (storage_balance.total.0 + available).into(),
+            available: available.into(),
+        })
+    }
+}
 
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
@@ -99,17 +511,79 @@ impl FungibleTokenMetadataProvider for Contract {
     }
 }
 
+#[near_bindgen]
+impl FungibleTokenCore for Contract {
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        require!(env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL, "More gas is required");
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+        self.internal_notify_receiver(sender_id, receiver_id, amount, msg).into()
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.token.ft_total_supply()
+    }
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.token.ft_balance_of(account_id)
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenResolver for Contract {
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        if let Some(used_amount) =
+            self.internal_resolve_transfer_to_sink(&sender_id, &receiver_id, amount.0)
+        {
+            return used_amount.into();
+        }
+        self.before_balance_change(&sender_id);
+        self.before_balance_change(&receiver_id);
+        let (used_amount, burned_amount) =
+            self.token.internal_ft_resolve_transfer(&sender_id, receiver_id.clone(), amount);
+        if burned_amount > 0 {
+            self.after_balance_change(Some(&receiver_id), None, burned_amount);
+            self.on_tokens_burned(sender_id, burned_amount);
+        } else if used_amount < amount.0 {
+            self.after_balance_change(Some(&receiver_id), Some(&sender_id), amount.0 - used_amount);
+        }
+        used_amount.into()
+    }
+}
+
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::MockedBlockchain;
     use near_sdk::{testing_env, Balance};
 
     use super::*;
 
-    const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+    pub(crate) const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+    /// Covers the storage of any record a test creates.
+    pub(crate) const DEPOSIT: Balance = 10_000_000_000_000_000_000_000;
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    pub(crate) fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
             .current_account_id(accounts(0))
@@ -118,11 +592,53 @@ mod tests {
         builder
     }
 
+    /// `new` only accepts the VRTX deployer as the predecessor.
+    pub(crate) fn deploy(context: &mut VMContextBuilder, owner_id: AccountId) -> Contract {
+        testing_env!(context
+            .predecessor_account_id(
+                "7f3d1be6c7322ca5a792900b3a42d7131251628b2bbe9e1515e7b4973ede4aee".parse().unwrap()
+            )
+            .build());
+        Contract::new_default_meta(owner_id, TOTAL_SUPPLY.into())
+    }
+
+    /// Registers each of `account_ids` with the minimum storage balance.
+    pub(crate) fn register(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        account_ids: &[AccountId],
+    ) {
+        for account_id in account_ids {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(contract.storage_balance_bounds().min.into())
+                .predecessor_account_id(account_id.clone())
+                .build());
+            contract.storage_deposit(None, None);
+        }
+    }
+
+    /// Deploys the contract owned by account 2, registers each of `account_ids`, and leaves the
+    /// owner as the caller with `deposit` attached.
+    pub(crate) fn setup_with(
+        account_ids: &[AccountId],
+        deposit: Balance,
+    ) -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(2));
+        let mut contract = deploy(&mut context, accounts(2));
+        register(&mut context, &mut contract, account_ids);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract)
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into(), TOTAL_SUPPLY.into());
+        let contract = deploy(&mut context, accounts(1));
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
         assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
@@ -139,8 +655,7 @@ mod tests {
     #[test]
     fn test_transfer() {
         let mut context = get_context(accounts(2));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(2).into(), TOTAL_SUPPLY.into());
+        let mut contract = deploy(&mut context, accounts(2));
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(contract.storage_balance_bounds().min.into())
@@ -0,0 +1,82 @@
+/*!
+State migration from the first deployed version.
+
+The first version kept only the token and its metadata. After the new code is deployed, the
+contract account calls `migrate` with the owner that the first version didn't have. The token
+and its metadata keep their storage, and the state of every feature added since starts empty,
+except `min_claimable_amount`, which starts at one whole token as for a new contract.
+*/
+use crate::*;
+
+/// The state of the first deployed version.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV1 {
+    token: FungibleToken,
+    metadata: LazyOption<FungibleTokenMetadata>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrades the state of the first version, with `owner_id` as the owner of the contract.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(owner_id: AccountId) -> Self {
+        let old: ContractV1 =
+            env::state_read().unwrap_or_else(|| env::panic_str("No state to migrate"));
+        Self::with_token(old.token, old.metadata, owner_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{get_context, register, TOTAL_SUPPLY};
+
+    /// Writes the state of the first version, with the whole supply held by accounts(1).
+    fn setup() -> VMContextBuilder {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut token = FungibleToken::new(b"a".to_vec());
+        token.internal_register_account(&accounts(1));
+        token.internal_deposit(&accounts(1), TOTAL_SUPPLY);
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "VRTX".to_string(),
+            symbol: "VRTX".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 24,
+        };
+        env::state_write(&ContractV1 {
+            token,
+            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
+        });
+        context
+    }
+
+    #[test]
+    fn migrate_keeps_balances() {
+        let mut context = setup();
+        let mut contract = Contract::migrate(accounts(2));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_metadata().symbol, "VRTX");
+
+        register(&mut context, &mut contract, &[accounts(3)]);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.ft_transfer(accounts(3), 1_000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_sink_account(Some(accounts(3)));
+        assert_eq!(contract.get_sink_account(), Some(accounts(3)));
+    }
+}
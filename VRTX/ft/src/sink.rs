@@ -0,0 +1,162 @@
+/*!
+Sink account for tokens that would otherwise be burned.
+
+When the owner sets a `sink_account`, the balance of an account closed with
+`storage_unregister(force = true)` is credited to the sink instead of being burned. So are the
+tokens that `ft_resolve_transfer` can't refund because the sender closed its account. Both emit
+an `ft_transfer` event to the sink. Without a sink, or while the sink itself is not registered,
+the tokens are burned as before.
+*/
+use crate::*;
+use near_contract_standards::fungible_token::events::FtTransfer;
+use near_sdk::PromiseResult;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SinkAccountSet<'a> {
+    sink_account: Option<&'a AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the account that gets the tokens which would otherwise be burned, or removes it.
+    #[payable]
+    pub fn set_sink_account(&mut self, sink_account: Option<AccountId>) {
+        self.only_owner();
+        assert_one_yocto();
+        if let Some(sink_id) = &sink_account {
+            require!(
+                self.token.accounts.contains_key(sink_id),
+                "The sink account is not registered"
+            );
+        }
+        self.sink_account = sink_account;
+        emit_nep297_event(
+            "sink_account_set",
+            &[SinkAccountSet { sink_account: self.sink_account.as_ref() }],
+        );
+    }
+
+    pub fn get_sink_account(&self) -> Option<AccountId> {
+        self.sink_account.clone()
+    }
+}
+
+impl Contract {
+    /// Returns the sink that can take tokens from `owner_id`, if it is set and registered.
+    pub(crate) fn internal_sink_account(&self, owner_id: &AccountId) -> Option<AccountId> {
+        self.sink_account
+            .clone()
+            .filter(|sink_id| sink_id != owner_id && self.token.accounts.contains_key(sink_id))
+    }
+
+    /// Credits `amount` that left the ledger with `owner_id` to `sink_id`, as a transfer.
+    pub(crate) fn internal_credit_sink(
+        &mut self,
+        owner_id: &AccountId,
+        sink_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        self.internal_deposit(sink_id, amount);
+        FtTransfer {
+            old_owner_id: owner_id,
+            new_owner_id: sink_id,
+            amount: &U128(amount),
+            memo: Some(memo),
+        }
+        .emit();
+    }
+
+    /// Resolves a transfer whose sender closed its account by moving the unused tokens from the
+    /// receiver to the sink, and returns the used amount like `internal_ft_resolve_transfer`.
+    /// Returns `None` if the sender is registered or there is no sink.
+    pub(crate) fn internal_resolve_transfer_to_sink(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Option<Balance> {
+        if self.token.accounts.contains_key(sender_id) {
+            return None;
+        }
+        let sink_id = self
+            .sink_account
+            .clone()
+            .filter(|sink_id| self.token.accounts.contains_key(sink_id))?;
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(amount, |unused| unused.0.min(amount)),
+            PromiseResult::Failed => amount,
+        };
+        let refund_amount = unused_amount.min(self.token.accounts.get(receiver_id).unwrap_or(0));
+        if refund_amount > 0 && sink_id != *receiver_id {
+            self.internal_transfer(
+                receiver_id,
+                &sink_id,
+                refund_amount,
+                Some("refund".to_string()),
+            );
+        }
+        Some(amount)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) =
+            setup_with(&[accounts(1), accounts(3), accounts(4)], ONE_YOCTO);
+        contract.set_sink_account(Some(accounts(3)));
+        contract.ft_transfer(accounts(1), 1_000.into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn closed_account_balance_goes_to_sink() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"ft_transfer\"")
+            && log.contains(&format!("\"new_owner_id\":\"{}\"", accounts(3)))));
+    }
+
+    #[test]
+    fn unrefundable_amount_goes_to_sink() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(4), 500.into(), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.storage_unregister(Some(true));
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"400\"".to_vec())],
+        );
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(4), 500.into());
+        assert_eq!(used.0, 500);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1_400);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    fn burns_without_sink() {
+        let (mut context, mut contract) = setup();
+        contract.set_sink_account(None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.storage_unregister(Some(true));
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 1_000);
+    }
+}
@@ -0,0 +1,169 @@
+/*!
+Balance snapshots for governance and airdrops.
+
+`snapshot` records the total supply and starts a new snapshot id. Account balances are not
+copied at that point. Instead the balance an account had at the latest snapshot is checkpointed
+on its first change after it, so transfers only pay for one extra write per snapshot. The account
+pays for its checkpoints out of its available storage balance, and a balance change it can't pay
+the checkpoint of fails.
+*/
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Checkpoint {
+    snapshot_id: u64,
+    balance: Balance,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SnapshotTaken {
+    snapshot_id: u64,
+    total_supply: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Takes a snapshot of all balances and returns its id.
+    #[payable]
+    pub fn snapshot(&mut self) -> u64 {
+        self.only_owner();
+        assert_one_yocto();
+        self.snapshot_id += 1;
+        self.total_supply_snapshots.insert(&self.snapshot_id, &self.token.total_supply);
+        emit_nep297_event(
+            "snapshot",
+            &[SnapshotTaken {
+                snapshot_id: self.snapshot_id,
+                total_supply: self.token.total_supply.into(),
+            }],
+        );
+        self.snapshot_id
+    }
+
+    pub fn get_current_snapshot_id(&self) -> u64 {
+        self.snapshot_id
+    }
+
+    pub fn balance_of_at(&self, account_id: AccountId, snapshot_id: u64) -> U128 {
+        self.assert_valid_snapshot(snapshot_id);
+        // The first checkpoint taken at or after `snapshot_id` holds the balance the account had
+        // when that snapshot was taken. Without one, the balance hasn't changed since.
+        let count = self.account_snapshot_counts.get(&account_id).unwrap_or(0);
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.internal_snapshot_checkpoint(&account_id, middle).snapshot_id < snapshot_id {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        if low == count {
+            self.token.ft_balance_of(account_id)
+        } else {
+            self.internal_snapshot_checkpoint(&account_id, low).balance.into()
+        }
+    }
+
+    pub fn total_supply_at(&self, snapshot_id: u64) -> U128 {
+        self.assert_valid_snapshot(snapshot_id);
+        self.total_supply_snapshots.get(&snapshot_id).unwrap().into()
+    }
+}
+
+impl Contract {
+    fn assert_valid_snapshot(&self, snapshot_id: u64) {
+        assert!(snapshot_id > 0 && snapshot_id <= self.snapshot_id, "Nonexistent snapshot id");
+    }
+
+    fn internal_snapshot_checkpoint(&self, account_id: &AccountId, index: u32) -> Checkpoint {
+        self.account_snapshots.get(&(account_id.clone(), index)).unwrap()
+    }
+
+    /// Checkpoints `balance` as the balance of `account_id` at the current snapshot, unless the
+    /// account has already changed since that snapshot was taken. The account pays for the
+    /// checkpoint.
+    pub(crate) fn internal_snapshot_balance(&mut self, account_id: &AccountId, balance: Balance) {
+        if self.snapshot_id == 0 {
+            return;
+        }
+        let count = self.account_snapshot_counts.get(account_id).unwrap_or(0);
+        if count > 0
+            && self.internal_snapshot_checkpoint(account_id, count - 1).snapshot_id
+                == self.snapshot_id
+        {
+            return;
+        }
+        let initial_storage_usage = env::storage_usage();
+        let checkpoint = Checkpoint { snapshot_id: self.snapshot_id, balance };
+        self.account_snapshots.insert(&(account_id.clone(), count), &checkpoint);
+        self.account_snapshot_counts.insert(account_id, &(count + 1));
+        self.internal_charge_storage(account_id, initial_storage_usage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1), accounts(3)], DEPOSIT);
+        for account_id in [accounts(1), accounts(2)].iter() {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(DEPOSIT)
+                .predecessor_account_id(account_id.clone())
+                .build());
+            contract.storage_deposit(None, None);
+        }
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn balance_of_at() {
+        let (_, mut contract) = setup();
+        let transfer_amount = TOTAL_SUPPLY / 4;
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        let first = contract.snapshot();
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+        let second = contract.snapshot();
+        let third = contract.snapshot();
+        contract.ft_transfer(accounts(1), transfer_amount.into(), None);
+
+        assert_eq!(contract.balance_of_at(accounts(2), first).0, TOTAL_SUPPLY - transfer_amount);
+        assert_eq!(contract.balance_of_at(accounts(1), first).0, transfer_amount);
+        assert_eq!(contract.balance_of_at(accounts(2), second).0, transfer_amount);
+        assert_eq!(contract.balance_of_at(accounts(2), third).0, transfer_amount);
+        assert_eq!(contract.balance_of_at(accounts(1), third).0, transfer_amount * 3);
+        assert_eq!(contract.total_supply_at(third).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "more of available storage balance")]
+    fn checkpoint_without_storage_fail() {
+        let (_, mut contract) = setup();
+        contract.snapshot();
+        contract.ft_transfer(accounts(3), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonexistent snapshot id")]
+    fn balance_of_at_fail() {
+        let (_, mut contract) = setup();
+        let snapshot_id = contract.snapshot();
+        contract.balance_of_at(accounts(1), snapshot_id + 1);
+    }
+}
@@ -0,0 +1,174 @@
+/*!
+Storage deposits.
+
+Calls that create a record, like an approval, an escrow or a vesting schedule, require an
+attached deposit to cover its storage and refund what is left of it. The account that paid for
+a record gets its storage cost back once the record is removed.
+
+`storage_deposit` keeps what is attached above the registration minimum as the available storage
+balance of the account, unless `registration_only` is set. It pays for the records this contract
+keeps about the balance of the account over time, which are written on its balance changes and
+can't be paid for out of the one yocto those calls attach: its balance snapshot checkpoints, its
+dividend record, and the vote checkpoints its balance changes add to its delegate. A balance change
+the account can't pay for them fails.
+`storage_withdraw` returns what is left, and so does `storage_unregister`.
+
+Calls that take a deposit for their own storage don't charge the caller for these records.
+*/
+use crate::*;
+
+impl Contract {
+    /// Adds the attached deposit to the available storage balance of `account_id`, after the
+    /// minimum storage balance if the account is not registered yet.
+    pub(crate) fn internal_storage_deposit(&mut self, account_id: AccountId) -> StorageBalance {
+        let mut amount = env::attached_deposit();
+        if !self.token.accounts.contains_key(&account_id) {
+            let min_balance = self.token.storage_balance_bounds().min.0;
+            require!(
+                amount >= min_balance,
+                "The attached deposit is less than the minimum storage balance"
+            );
+            self.token.internal_register_account(&account_id);
+            amount -= min_balance;
+        }
+        self.internal_deposit_available_storage(&account_id, amount);
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Adds `amount` to the available storage balance of the registered `account_id`.
+    pub(crate) fn internal_deposit_available_storage(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        let initial_storage_usage = env::storage_usage();
+        let available = self.internal_available_storage(account_id);
+        self.available_storage.insert(account_id, &available);
+        // The entry itself is paid out of the first deposit.
+        let cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        require!(amount >= cost, "The attached deposit doesn't cover its own storage");
+        self.available_storage.insert(account_id, &(available + amount - cost));
+    }
+
+    pub(crate) fn internal_storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let storage_balance = self.storage_balance_of(account_id.clone()).unwrap_or_else(|| {
+            env::panic_str(&format!("The account {} is not registered", account_id))
+        });
+        let available = storage_balance.available.0;
+        let amount = amount.map_or(available, |amount| amount.0);
+        require!(amount <= available, "The amount is greater than the available storage balance");
+        if amount > 0 {
+            self.available_storage.insert(&account_id, &(available - amount));
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    pub(crate) fn internal_available_storage(&self, account_id: &AccountId) -> Balance {
+        self.available_storage.get(account_id).unwrap_or(0)
+    }
+
+    /// Charges `account_id` for the storage added since `initial_storage_usage` by the records
+    /// about its balance, and panics if it can't pay for it. The contract pays for its own.
+    pub(crate) fn internal_charge_storage(
+        &mut self,
+        account_id: &AccountId,
+        initial_storage_usage: StorageUsage,
+    ) {
+        let storage_usage = env::storage_usage();
+        if storage_usage <= initial_storage_usage {
+            return;
+        }
+        let bytes = storage_usage - initial_storage_usage;
+        if account_id != &env::current_account_id() {
+            let cost = Balance::from(bytes) * env::storage_byte_cost();
+            let available = self.internal_available_storage(account_id);
+            if available < cost {
+                env::panic_str(&format!(
+                    "The account {} needs {} yoctoNEAR more of available storage balance",
+                    account_id,
+                    cost - available
+                ));
+            }
+            self.available_storage.insert(account_id, &(available - cost));
+        }
+        self.charged_storage_usage += bytes;
+    }
+
+    /// Sends a closed account what is left of its available storage balance, with the storage
+    /// of the entry.
+    pub(crate) fn internal_refund_available_storage(&mut self, account_id: &AccountId) {
+        let initial_storage_usage = env::storage_usage();
+        if let Some(available) = self.available_storage.remove(account_id) {
+            let refund = available
+                + Balance::from(initial_storage_usage - env::storage_usage())
+                    * env::storage_byte_cost();
+            Promise::new(account_id.clone()).transfer(refund);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_NEAR, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::setup_with;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        setup_with(&[], 0)
+    }
+
+    #[test]
+    fn deposit_above_minimum_is_available() {
+        let (mut context, mut contract) = setup();
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(min_balance + ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let storage_balance = contract.storage_deposit(None, None);
+        let available = storage_balance.available.0;
+        assert!(available > ONE_NEAR - ONE_NEAR / 1_000 && available < ONE_NEAR);
+        assert_eq!(storage_balance.total.0, min_balance + available);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .build());
+        let storage_balance = contract.storage_withdraw(Some(U128(available / 2)));
+        assert_eq!(storage_balance.available.0, available - available / 2);
+    }
+
+    #[test]
+    fn registration_only_refunds_the_rest() {
+        let (mut context, mut contract) = setup();
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(min_balance + ONE_NEAR)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let storage_balance = contract.storage_deposit(None, Some(true));
+        assert_eq!(storage_balance.available.0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount is greater than the available storage balance")]
+    fn storage_withdraw_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.storage_withdraw(Some(U128(1)));
+    }
+}
@@ -0,0 +1,346 @@
+/*!
+Recurring transfers executed by keepers.
+
+A subscriber authorizes a payee to receive `amount` every `period` nanoseconds, for up to
+`max_periods` periods. The first period is due when the subscription is created. Any keeper
+account can call `execute_due` to pay the periods that are due, up to
+`MAX_PERIODS_PER_EXECUTION` per subscription and call. A period stays due while the subscriber
+can't pay it. The subscriber or the payee can `cancel_subscription` at any time, which first
+pays what is due if it can. Once the last period is paid, the subscription is removed and its
+storage refunded to the subscriber.
+
+The record keeps the time each period was executed, with a slot for every period from the
+start, so all of its storage is paid for when the subscription is created.
+*/
+use crate::*;
+use near_sdk::json_types::U64;
+
+const MAX_SUBSCRIPTION_PERIODS: u32 = 120;
+const MAX_PERIODS_PER_EXECUTION: u32 = 12;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Subscription {
+    subscriber_id: AccountId,
+    payee_id: AccountId,
+    amount: Balance,
+    period: u64,
+    max_periods: u32,
+    start: u64,
+    executed_periods: u32,
+    /// Execution time of each period, 0 for the periods that were not executed yet.
+    history: Vec<u64>,
+    storage_cost: Balance,
+}
+
+impl Subscription {
+    fn next_due_at(&self) -> Option<u64> {
+        if self.executed_periods < self.max_periods {
+            Some(self.start + u64::from(self.executed_periods) * self.period)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubscriptionView {
+    subscriber_id: AccountId,
+    payee_id: AccountId,
+    amount: U128,
+    period: U64,
+    max_periods: u32,
+    start: U64,
+    executed_periods: u32,
+    next_due_at: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SubscriptionCreated<'a> {
+    subscription_id: u64,
+    subscriber_id: &'a AccountId,
+    payee_id: &'a AccountId,
+    amount: U128,
+    period: U64,
+    max_periods: u32,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SubscriptionExecuted {
+    subscription_id: u64,
+    period_index: u32,
+    amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SubscriptionCanceled<'a> {
+    subscription_id: u64,
+    canceled_by: &'a AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Authorizes `payee_id` to receive `amount` of the caller's tokens every `period`
+    /// nanoseconds, and returns the id of the subscription.
+    #[payable]
+    pub fn create_subscription(
+        &mut self,
+        payee_id: AccountId,
+        amount: U128,
+        period: U64,
+        max_periods: u32,
+    ) -> u64 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let subscriber_id = env::predecessor_account_id();
+        require!(subscriber_id != payee_id, "Sender and receiver should be different");
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(period.0 > 0, "Period must be positive");
+        require!(
+            max_periods > 0 && max_periods <= MAX_SUBSCRIPTION_PERIODS,
+            "Must have between 1 and 120 periods"
+        );
+        self.token.internal_unwrap_balance_of(&payee_id);
+        let initial_storage_usage = env::storage_usage();
+
+        let subscription_id = self.next_subscription_id;
+        self.next_subscription_id += 1;
+        let mut subscription = Subscription {
+            subscriber_id: subscriber_id.clone(),
+            payee_id: payee_id.clone(),
+            amount: amount.0,
+            period: period.0,
+            max_periods,
+            start: env::block_timestamp(),
+            executed_periods: 0,
+            history: vec![0; max_periods as usize],
+            storage_cost: 0,
+        };
+        self.subscriptions.insert(&subscription_id, &subscription);
+        subscription.storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        self.subscriptions.insert(&subscription_id, &subscription);
+
+        emit_nep297_event(
+            "subscription_created",
+            &[SubscriptionCreated {
+                subscription_id,
+                subscriber_id: &subscriber_id,
+                payee_id: &payee_id,
+                amount,
+                period,
+                max_periods,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        subscription_id
+    }
+
+    /// Pays the periods that are due for the given subscriptions, and returns how many periods
+    /// were paid. Anyone can execute them.
+    pub fn execute_due(&mut self, subscription_ids: Vec<u64>) -> u32 {
+        let mut executed = vec![];
+        for subscription_id in subscription_ids {
+            if let Some(mut subscription) = self.subscriptions.get(&subscription_id) {
+                self.internal_execute_subscription(
+                    subscription_id,
+                    &mut subscription,
+                    &mut executed,
+                );
+            }
+        }
+        if !executed.is_empty() {
+            emit_nep297_event("subscription_executed", &executed);
+        }
+        executed.len() as u32
+    }
+
+    /// Pays what is due if the subscriber can, then removes the subscription and refunds its
+    /// storage to the subscriber. The subscriber or the payee can cancel.
+    #[payable]
+    pub fn cancel_subscription(&mut self, subscription_id: u64) {
+        assert_one_yocto();
+        let mut subscription = self
+            .subscriptions
+            .get(&subscription_id)
+            .unwrap_or_else(|| env::panic_str("Subscription not found"));
+        let canceled_by = env::predecessor_account_id();
+        require!(
+            canceled_by == subscription.subscriber_id || canceled_by == subscription.payee_id,
+            "Only the subscriber or the payee can cancel the subscription"
+        );
+        let mut executed = vec![];
+        self.internal_execute_subscription(subscription_id, &mut subscription, &mut executed);
+        if !executed.is_empty() {
+            emit_nep297_event("subscription_executed", &executed);
+        }
+
+        // Paying the last period already removed it.
+        if subscription.next_due_at().is_some() {
+            self.internal_close_subscription(subscription_id, &subscription);
+        }
+        emit_nep297_event(
+            "subscription_canceled",
+            &[SubscriptionCanceled { subscription_id, canceled_by: &canceled_by }],
+        );
+    }
+
+    pub fn get_subscription(&self, subscription_id: u64) -> Option<SubscriptionView> {
+        self.subscriptions.get(&subscription_id).map(|subscription| SubscriptionView {
+            next_due_at: subscription.next_due_at().map(U64),
+            subscriber_id: subscription.subscriber_id,
+            payee_id: subscription.payee_id,
+            amount: subscription.amount.into(),
+            period: subscription.period.into(),
+            max_periods: subscription.max_periods,
+            start: subscription.start.into(),
+            executed_periods: subscription.executed_periods,
+        })
+    }
+
+    /// Returns when the next period is due, or `None` once all periods were paid.
+    pub fn get_next_due_time(&self, subscription_id: u64) -> Option<U64> {
+        self.subscriptions.get(&subscription_id).and_then(|s| s.next_due_at()).map(U64)
+    }
+
+    /// Returns the execution time of each period that was paid, in order.
+    pub fn get_subscription_history(&self, subscription_id: u64) -> Vec<U64> {
+        self.subscriptions.get(&subscription_id).map_or(vec![], |subscription| {
+            let executed_periods = subscription.executed_periods as usize;
+            subscription.history[..executed_periods].iter().map(|time| U64(*time)).collect()
+        })
+    }
+}
+
+impl Contract {
+    /// Pays the due periods of the subscription while the subscriber can, and stores it, or
+    /// closes it once all periods were paid.
+    fn internal_execute_subscription(
+        &mut self,
+        subscription_id: u64,
+        subscription: &mut Subscription,
+        executed: &mut Vec<SubscriptionExecuted>,
+    ) {
+        let now = env::block_timestamp();
+        let mut periods = 0;
+        while periods < MAX_PERIODS_PER_EXECUTION
+            && matches!(subscription.next_due_at(), Some(due_at) if due_at <= now)
+            && self.internal_can_pay_subscription(subscription)
+        {
+            self.internal_transfer(
+                &subscription.subscriber_id,
+                &subscription.payee_id,
+                subscription.amount,
+                Some("subscription".to_string()),
+            );
+            subscription.history[subscription.executed_periods as usize] = now;
+            executed.push(SubscriptionExecuted {
+                subscription_id,
+                period_index: subscription.executed_periods,
+                amount: subscription.amount.into(),
+            });
+            subscription.executed_periods += 1;
+            periods += 1;
+        }
+        if subscription.next_due_at().is_none() {
+            self.internal_close_subscription(subscription_id, subscription);
+        } else if periods > 0 {
+            self.subscriptions.insert(&subscription_id, subscription);
+        }
+    }
+
+    /// Removes the subscription and refunds its storage to the subscriber.
+    fn internal_close_subscription(&mut self, subscription_id: u64, subscription: &Subscription) {
+        self.subscriptions.remove(&subscription_id);
+        if subscription.storage_cost > 0 {
+            Promise::new(subscription.subscriber_id.clone()).transfer(subscription.storage_cost);
+        }
+    }
+
+    fn internal_can_pay_subscription(&self, subscription: &Subscription) -> bool {
+        self.token.accounts.contains_key(&subscription.payee_id)
+            && self.ft_balance_of(subscription.subscriber_id.clone()).0 >= subscription.amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT};
+
+    const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+    const AMOUNT: Balance = 1_000;
+
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1)], DEPOSIT);
+        testing_env!(context.block_timestamp(DAY).build());
+        contract.create_subscription(accounts(1), AMOUNT.into(), U64(30 * DAY), 3);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(3))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn execute_due_periods() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.execute_due(vec![0, 1]), 1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, AMOUNT);
+        assert_eq!(contract.get_next_due_time(0), Some(U64(31 * DAY)));
+        assert_eq!(contract.execute_due(vec![0]), 0);
+
+        testing_env!(context.block_timestamp(40 * DAY).build());
+        assert_eq!(contract.execute_due(vec![0]), 1);
+        assert_eq!(contract.get_subscription_history(0), vec![U64(DAY), U64(40 * DAY)]);
+
+        testing_env!(context.block_timestamp(80 * DAY).build());
+        assert_eq!(contract.execute_due(vec![0]), 1);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 3 * AMOUNT);
+        assert_eq!(contract.get_next_due_time(0), None);
+    }
+
+    #[test]
+    fn completed_subscription_refunds_storage() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(80 * DAY).build());
+        assert_eq!(contract.execute_due(vec![0]), 3);
+        assert!(contract.get_subscription(0).is_none());
+        let refunds: Vec<_> = get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == accounts(2))
+            .collect();
+        assert_eq!(refunds.len(), 1);
+        assert!(matches!(refunds[0].actions[..], [VmAction::Transfer { deposit }] if deposit > 0));
+
+        testing_env!(context.block_timestamp(200 * DAY).build());
+        assert_eq!(contract.execute_due(vec![0]), 0);
+    }
+
+    #[test]
+    fn cancel_pays_due_periods() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.cancel_subscription(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, AMOUNT);
+        assert!(contract.get_subscription(0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the subscriber or the payee can cancel the subscription")]
+    fn cancel_subscription_fail() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(ONE_YOCTO).build());
+        contract.cancel_subscription(0);
+    }
+}
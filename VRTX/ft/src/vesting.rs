@@ -0,0 +1,415 @@
+/*!
+Linear vesting with a cliff.
+
+`create_vesting` locks `total` tokens of the caller for a beneficiary, and returns the id of the
+schedule. A beneficiary can have up to 100 open schedules, from the same or different grantors,
+and so can a grantor.
+Nothing vests before `start + cliff`. After that the tokens vest linearly from `start`, and all
+of them have vested at `start + duration`. The beneficiary calls `release` to get what has vested
+so far. If the schedule is revocable, the grantor can `revoke` it to get the unvested tokens
+back, and the beneficiary keeps what had vested.
+*/
+use crate::*;
+use circulating::Lock;
+use near_sdk::json_types::U64;
+
+const DEFAULT_VESTINGS_LIMIT: u64 = 50;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Vesting {
+    grantor_id: AccountId,
+    beneficiary_id: AccountId,
+    total: Balance,
+    released: Balance,
+    start: u64,
+    cliff: u64,
+    duration: u64,
+    revocable: bool,
+    revoked: bool,
+    storage_cost: Balance,
+}
+
+impl Vesting {
+    fn vested(&self) -> Balance {
+        let now = env::block_timestamp();
+        if self.revoked || now >= self.start + self.duration {
+            self.total
+        } else if now < self.start + self.cliff {
+            0
+        } else {
+            mul_div(self.total, (now - self.start).into(), self.duration.into())
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingView {
+    vesting_id: u64,
+    grantor_id: AccountId,
+    beneficiary_id: AccountId,
+    total: U128,
+    released: U128,
+    start: U64,
+    cliff: U64,
+    duration: U64,
+    revocable: bool,
+    revoked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct VestingCreated<'a> {
+    vesting_id: u64,
+    grantor_id: &'a AccountId,
+    beneficiary_id: &'a AccountId,
+    total: U128,
+    start: U64,
+    cliff: U64,
+    duration: U64,
+    revocable: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct VestingChanged<'a> {
+    vesting_id: u64,
+    beneficiary_id: &'a AccountId,
+    amount: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `total` tokens of the caller to vest for `beneficiary_id`, and returns the id of the
+    /// schedule. `cliff` and `duration` are in nanoseconds from `start`.
+    #[payable]
+    pub fn create_vesting(
+        &mut self,
+        beneficiary_id: AccountId,
+        total: U128,
+        start: U64,
+        cliff: U64,
+        duration: U64,
+        revocable: bool,
+    ) -> u64 {
+        require!(env::attached_deposit() > 0, "Requires attached deposit of at least 1 yoctoNEAR");
+        let grantor_id = env::predecessor_account_id();
+        require!(total.0 > 0, "The amount should be a positive number");
+        require!(duration.0 > 0, "Duration must be positive");
+        require!(cliff.0 <= duration.0, "The cliff must not be longer than the duration");
+        require!(start.0.checked_add(duration.0).is_some(), "The vesting schedule ends too late");
+        let initial_storage_usage = env::storage_usage();
+        self.internal_lock_escrow(Lock::Vesting, &grantor_id, total.0, "vesting");
+
+        let vesting_id = self.next_vesting_id;
+        self.next_vesting_id += 1;
+        let mut vesting = Vesting {
+            grantor_id: grantor_id.clone(),
+            beneficiary_id: beneficiary_id.clone(),
+            total: total.0,
+            released: 0,
+            start: start.0,
+            cliff: cliff.0,
+            duration: duration.0,
+            revocable,
+            revoked: false,
+            storage_cost: 0,
+        };
+        self.vestings.insert(&vesting_id, &vesting);
+        push_account_ids(&mut self.account_vestings, &[&grantor_id, &beneficiary_id], vesting_id);
+        let vesting_balance = self.internal_vesting_balance(&beneficiary_id) + total.0;
+        self.vesting_balances.insert(&beneficiary_id, &vesting_balance);
+        vesting.storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        self.vestings.insert(&vesting_id, &vesting);
+        emit_nep297_event(
+            "vesting_created",
+            &[VestingCreated {
+                vesting_id,
+                grantor_id: &grantor_id,
+                beneficiary_id: &beneficiary_id,
+                total,
+                start,
+                cliff,
+                duration,
+                revocable,
+            }],
+        );
+        self.internal_settle_storage(initial_storage_usage);
+        vesting_id
+    }
+
+    /// Pays out the tokens of the schedule that have vested and were not released yet. Only the
+    /// beneficiary can release.
+    #[payable]
+    pub fn release(&mut self, vesting_id: u64) -> U128 {
+        assert_one_yocto();
+        let mut vesting = self.internal_unwrap_vesting(vesting_id);
+        let beneficiary_id = vesting.beneficiary_id.clone();
+        require!(
+            env::predecessor_account_id() == beneficiary_id,
+            "Only the beneficiary can release the vesting schedule"
+        );
+        let amount = vesting.vested() - vesting.released;
+        require!(amount > 0, "Nothing to release");
+        vesting.released += amount;
+        self.internal_save_vesting(vesting_id, vesting);
+        self.internal_reduce_vesting_balance(&beneficiary_id, amount);
+
+        self.internal_release_escrow(
+            Lock::Vesting,
+            &beneficiary_id,
+            amount,
+            Some("vesting".to_string()),
+        );
+        emit_nep297_event(
+            "vesting_released",
+            &[VestingChanged {
+                vesting_id,
+                beneficiary_id: &beneficiary_id,
+                amount: amount.into(),
+            }],
+        );
+        amount.into()
+    }
+
+    /// Returns the unvested tokens of a revocable schedule to its grantor. The beneficiary can
+    /// still release what had vested.
+    #[payable]
+    pub fn revoke(&mut self, vesting_id: u64) -> U128 {
+        assert_one_yocto();
+        let mut vesting = self.internal_unwrap_vesting(vesting_id);
+        require!(
+            env::predecessor_account_id() == vesting.grantor_id,
+            "Only the grantor can revoke the vesting schedule"
+        );
+        require!(vesting.revocable, "The vesting schedule is not revocable");
+        require!(!vesting.revoked, "The vesting schedule was already revoked");
+        let vested = vesting.vested();
+        let unvested = vesting.total - vested;
+        vesting.total = vested;
+        vesting.revoked = true;
+        let grantor_id = vesting.grantor_id.clone();
+        let beneficiary_id = vesting.beneficiary_id.clone();
+        self.internal_save_vesting(vesting_id, vesting);
+        self.internal_reduce_vesting_balance(&beneficiary_id, unvested);
+
+        if unvested > 0 {
+            self.internal_release_escrow(
+                Lock::Vesting,
+                &grantor_id,
+                unvested,
+                Some("vesting revoked".to_string()),
+            );
+        }
+        emit_nep297_event(
+            "vesting_revoked",
+            &[VestingChanged {
+                vesting_id,
+                beneficiary_id: &beneficiary_id,
+                amount: unvested.into(),
+            }],
+        );
+        unvested.into()
+    }
+
+    pub fn vested_amount(&self, vesting_id: u64) -> U128 {
+        self.vestings.get(&vesting_id).map_or(0, |vesting| vesting.vested()).into()
+    }
+
+    pub fn releasable_amount(&self, vesting_id: u64) -> U128 {
+        self.vestings
+            .get(&vesting_id)
+            .map_or(0, |vesting| vesting.vested() - vesting.released)
+            .into()
+    }
+
+    pub fn get_vesting(&self, vesting_id: u64) -> Option<VestingView> {
+        self.vestings.get(&vesting_id).map(|vesting| vesting_view(vesting_id, vesting))
+    }
+
+    /// Returns the schedules that `account_id` granted or benefits from, oldest first.
+    pub fn get_vestings(
+        &self,
+        account_id: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<VestingView> {
+        let limit = limit.unwrap_or(DEFAULT_VESTINGS_LIMIT);
+        account_ids_page(&self.account_vestings, &account_id, from_index, limit)
+            .into_iter()
+            .map(|vesting_id| vesting_view(vesting_id, self.vestings.get(&vesting_id).unwrap()))
+            .collect()
+    }
+}
+
+fn vesting_view(vesting_id: u64, vesting: Vesting) -> VestingView {
+    VestingView {
+        vesting_id,
+        grantor_id: vesting.grantor_id,
+        beneficiary_id: vesting.beneficiary_id,
+        total: vesting.total.into(),
+        released: vesting.released.into(),
+        start: vesting.start.into(),
+        cliff: vesting.cliff.into(),
+        duration: vesting.duration.into(),
+        revocable: vesting.revocable,
+        revoked: vesting.revoked,
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_vesting(&self, vesting_id: u64) -> Vesting {
+        self.vestings.get(&vesting_id).unwrap_or_else(|| env::panic_str("No vesting schedule"))
+    }
+
+    /// Returns the tokens of the schedules of `account_id` as a beneficiary that were not
+    /// released yet, whether they have vested or not.
+    pub(crate) fn internal_vesting_balance(&self, account_id: &AccountId) -> Balance {
+        self.vesting_balances.get(account_id).unwrap_or(0)
+    }
+
+    fn internal_reduce_vesting_balance(&mut self, beneficiary_id: &AccountId, amount: Balance) {
+        let vesting_balance = self.internal_vesting_balance(beneficiary_id) - amount;
+        if vesting_balance == 0 {
+            self.vesting_balances.remove(beneficiary_id);
+        } else {
+            self.vesting_balances.insert(beneficiary_id, &vesting_balance);
+        }
+    }
+
+    /// Stores the schedule, or removes it once everything was released and refunds its storage
+    /// to the grantor.
+    fn internal_save_vesting(&mut self, vesting_id: u64, vesting: Vesting) {
+        if vesting.released < vesting.total {
+            self.vestings.insert(&vesting_id, &vesting);
+            return;
+        }
+        self.vestings.remove(&vesting_id);
+        remove_account_ids(
+            &mut self.account_vestings,
+            &[&vesting.grantor_id, &vesting.beneficiary_id],
+            vesting_id,
+        );
+        if vesting.storage_cost > 0 {
+            Promise::new(vesting.grantor_id).transfer(vesting.storage_cost);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, ONE_YOCTO};
+
+    use super::*;
+    use crate::tests::{setup_with, DEPOSIT, TOTAL_SUPPLY};
+
+    const SECOND: u64 = 1_000_000_000;
+    const TOTAL: Balance = 1_000;
+
+    fn setup(revocable: bool) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_with(&[accounts(1), accounts(3)], DEPOSIT);
+        contract.create_vesting(
+            accounts(1),
+            TOTAL.into(),
+            U64(100 * SECOND),
+            U64(10 * SECOND),
+            U64(40 * SECOND),
+            revocable,
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(ONE_YOCTO)
+            .predecessor_account_id(accounts(1))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn release_across_cliff_and_end() {
+        let (mut context, mut contract) = setup(false);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - TOTAL);
+        testing_env!(context.block_timestamp(110 * SECOND - 1).build());
+        assert_eq!(contract.vested_amount(0).0, 0);
+
+        testing_env!(context.block_timestamp(110 * SECOND).build());
+        assert_eq!(contract.vested_amount(0).0, TOTAL / 4);
+        assert_eq!(contract.release(0).0, TOTAL / 4);
+        assert_eq!(contract.releasable_amount(0).0, 0);
+
+        testing_env!(context.block_timestamp(130 * SECOND).build());
+        assert_eq!(contract.releasable_amount(0).0, TOTAL / 2);
+
+        testing_env!(context.block_timestamp(150 * SECOND).build());
+        assert_eq!(contract.vested_amount(0).0, TOTAL);
+        assert_eq!(contract.release(0).0, TOTAL * 3 / 4);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL);
+        assert!(contract.get_vesting(0).is_none());
+    }
+
+    #[test]
+    fn revoke_returns_unvested() {
+        let (mut context, mut contract) = setup(true);
+        testing_env!(context
+            .block_timestamp(120 * SECOND)
+            .predecessor_account_id(accounts(2))
+            .build());
+        assert_eq!(contract.revoke(0).0, TOTAL / 2);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - TOTAL / 2);
+
+        testing_env!(context
+            .block_timestamp(150 * SECOND)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.vested_amount(0).0, TOTAL / 2);
+        assert_eq!(contract.release(0).0, TOTAL / 2);
+        assert!(contract.get_vesting(0).is_none());
+    }
+
+    #[test]
+    fn schedules_from_several_grantors() {
+        let (mut context, mut contract) = setup(false);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(3), 1.into(), None);
+        testing_env!(context.attached_deposit(DEPOSIT).predecessor_account_id(accounts(3)).build());
+        let vesting_id =
+            contract.create_vesting(accounts(1), 1.into(), U64(0), U64(0), U64(u64::MAX), false);
+        assert_eq!(vesting_id, 1);
+        assert_eq!(contract.get_vestings(accounts(1), None, None).len(), 2);
+        assert_eq!(contract.get_vestings(accounts(3), None, None)[0].vesting_id, 1);
+
+        testing_env!(context
+            .attached_deposit(ONE_YOCTO)
+            .block_timestamp(150 * SECOND)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.release(0).0, TOTAL);
+        assert_eq!(contract.get_vestings(accounts(1), None, None)[0].vesting_id, 1);
+        assert!(contract.get_vestings(accounts(2), None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "The vesting schedule ends too late")]
+    fn create_vesting_fail() {
+        let (mut context, mut contract) = setup(false);
+        testing_env!(context.attached_deposit(DEPOSIT).predecessor_account_id(accounts(2)).build());
+        contract.create_vesting(accounts(1), 1.into(), U64(1), U64(0), U64(u64::MAX), false);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing to release")]
+    fn release_before_cliff_fail() {
+        let (mut context, mut contract) = setup(false);
+        testing_env!(context.block_timestamp(110 * SECOND - 1).build());
+        contract.release(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The vesting schedule is not revocable")]
+    fn revoke_fail() {
+        let (mut context, mut contract) = setup(false);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.revoke(0);
+    }
+}